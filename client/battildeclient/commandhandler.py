@@ -1,6 +1,8 @@
 
 import json
 
+from battildeclient.common import messages
+
 try:
     import hy
 except ImportError as e:
@@ -22,6 +24,9 @@ class CommandHandler:
             "say": self.say,
             "pick": self.pick,
             "chat": self.chat,
+            "me": lambda text: self.client.sendChat(text, messages.MeMessage),
+            "t": lambda text: self.client.sendChat(text, messages.TeamChatMessage),
+            "msg": self.privateMessage,
             "log": self.log,
             "do": self.do,
             "runinput": self.runInput,
@@ -44,6 +49,16 @@ class CommandHandler:
             "buy": lambda option: self.interact("buy "+option),
             "nextweapon": lambda: self.input("nextweapon"),
             "previousweapon": lambda: self.input("previousweapon"),
+            "team": lambda team: self.input({"team": int(team)}),
+            "vote": lambda option: self.input({"vote": option}),
+            "reload": lambda: self.input("reload"),
+            "tile": lambda name: self.input({"placetile": name}),
+            "creature": lambda name: self.input({"placecreature": name}),
+            "monsterspawn": lambda: self.input("togglemonsterspawn"),
+            "spawnpoint": lambda: self.input("setspawnpoint"),
+            "erase": lambda: self.input("erase"),
+            "savemap": lambda name: self.input({"savemap": name}),
+            "password": lambda password: self.client.sendMessage(messages.PasswordMessage(password)),
             "help": self.toggleHelp
         }
         
@@ -85,6 +100,12 @@ class CommandHandler:
     def chat(self, text):
         self.client.sendChat( text)
     
+    def privateMessage(self, arg):
+        name, _sep, text = arg.partition(' ')
+        if not name or not text:
+            raise InvalidCommandException("usage: /msg <player> <text>")
+        self.client.sendChat(text, messages.PrivateMessage, name)
+    
     
     def log(self, text):
         self.client.log(text)
@@ -56,7 +56,7 @@ class IntroductionMessage(ClientToServerMessage):
     categories = {"Lu", "Ll", "Lt", "Lm", "Lo", "Nd", "Nl", "No", "Pc"}
     
     
-    def __init__(self, name, sprite):
+    def __init__(self, name, sprite, password=None):
         assert isinstance(name, str), InvalidNameError("name must be a string")
         assert (len(name) > 0), InvalidNameError("name needs at least one character")
         assert (len(bytes(name, "utf-8")) <= 256), InvalidNameError("name may not be longer than 256 utf8 bytes")
@@ -66,9 +66,23 @@ class IntroductionMessage(ClientToServerMessage):
                 assert category in self.categories, InvalidNameError("all name caracters must be in these unicode categories: " + "|".join(self.categories) + " ")
         self.name = name
         self.sprite = sprite
+        self.password = password
     
     def body(self):
-        return [self.name, self.sprite]
+        if self.password is None:
+            return [self.name, self.sprite]
+        return [self.name, self.sprite, self.password]
+
+
+class PasswordMessage(ClientToServerMessage):
+    
+    typename = "password"
+    
+    def __init__(self, password):
+        self.password = password
+    
+    def body(self):
+        return self.password
 
 
 
@@ -96,6 +110,25 @@ class ChatMessage(ClientToServerMessage):
     def body(self):
         return self.text
 
+class MeMessage(ChatMessage):
+    
+    typename = "me"
+
+class TeamChatMessage(ChatMessage):
+    
+    typename = "teamchat"
+
+class PrivateMessage(ChatMessage):
+    
+    typename = "msg"
+    
+    def __init__(self, name, text):
+        super().__init__(text)
+        self.name = name
+    
+    def body(self):
+        return [self.name, self.text]
+
 
 
 class ServerToClientMessage(Message):
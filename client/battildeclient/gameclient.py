@@ -40,9 +40,9 @@ class Client:
         message = messages.InputMessage(inp)
         self.sendMessage(message)
     
-    def sendChat(self, text):
+    def sendChat(self, text, messageType=messages.ChatMessage, *args):
         try:
-            self.sendMessage(messages.ChatMessage(text))
+            self.sendMessage(messageType(*args, text))
         except messages.InvalidMessageError as e:
             self.log(e.description)
     
@@ -137,6 +137,8 @@ class Client:
             self.display.setWeapons(weapons, selected)
         if msgType == "ground":
             self.display.setGround(msg[1])
+        if msgType == "scores":
+            self.display.showInfo("Score:\n" + "\n".join("{}: {}".format(name, score) for (name, score) in msg[1]))
         if msgType == "message":
             text, type = msg[1:3]
             self.log(text, type)
@@ -15,7 +15,7 @@ from battildeclient.common import messages
 
 def main(argv=None):
     
-    (name, socketType, address, keybindings, characters, colours, logfile, ratuil_screen, ratuil_args, sprite) = parse_args(argv)
+    (name, socketType, address, keybindings, characters, colours, logfile, ratuil_screen, ratuil_args, sprite, password) = parse_args(argv)
     
     if ratuil_screen == "ansibuffered":
         from ratuil.bufferedscreen import Screen
@@ -33,7 +33,7 @@ def main(argv=None):
         print("ERROR: Could not connect to server.\nAre you sure that the server is running and that you're connecting to the right address?", file=sys.stderr)
         return
     
-    if not introduce(connection, name, sprite):
+    if not introduce(connection, name, sprite, password):
         return
     error = None
     closeMessage = None
@@ -68,8 +68,8 @@ def main(argv=None):
         print(closeMessage, file=sys.stderr)
 
 
-def introduce(connection, name, sprite):
-    connection.send(messages.IntroductionMessage(name, "player_"+sprite))
+def introduce(connection, name, sprite, password):
+    connection.send(messages.IntroductionMessage(name, "player_"+sprite, password))
     print("introducing to server as {}".format(name))
     response = connection.receive()
     if response is None:
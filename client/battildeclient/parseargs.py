@@ -24,6 +24,7 @@ def parse_args(argv):
     parser = argparse.ArgumentParser(
         description="The client to Battilde. Run this to connect to to the server.",
         formatter_class=argparse.RawDescriptionHelpFormatter)
+    parser.add_argument('-p', '--password', help='Ask for the password of your name, for names that are protected with /password', action="store_true")
     parser.add_argument('-n', '--name', help='Your player name (must be unique!). Defaults to username. All characters must be unicode letters, numbers or connection puctuation. The maximum size of a name is 256 bytes when encoded as utf8', default=None)
     parser.add_argument("-a", "--address", help="The address of the socket. When the socket type is 'abstract' this is just a name. When it is 'unix' this is a filename. When it is 'inet' is should be in the format 'address:port', eg 'localhost:8080'. Defaults depends on the socket type")
     parser.add_argument("-s", "--socket", help="the socket type. 'unix' is unix domain sockets, 'abstract' is abstract unix domain sockets and 'inet' is inet sockets. ", choices=["abstract", "unix", "inet", "inet4", "inet6"], default=("abstract" if sys.platform == "linux" else "inet"))
@@ -71,4 +72,8 @@ def parse_args(argv):
             char = letters[0]
         sprite = "{}-{}".format(colour, char)
     
-    return (name, args.socket, address, keybindings, charmap, colours, args.logfile, args.ratuil_screen, {"always_reset": args.reset_style, "blink_bright_background": args.blink_bright_background}, sprite)
+    password = None
+    if args.password:
+        password = getpass.getpass("password for {}: ".format(name))
+    
+    return (name, args.socket, address, keybindings, charmap, colours, args.logfile, args.ratuil_screen, {"always_reset": args.reset_style, "blink_bright_background": args.blink_bright_background}, sprite, password)
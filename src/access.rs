@@ -0,0 +1,191 @@
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+use crate::{
+	Result,
+	aerr,
+	errors::AnyError,
+	util::write_file_safe
+};
+
+/** Where a connection comes from, as far as the server can tell */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Peer {
+	Ip(IpAddr),
+	Uid(u32),
+	Unknown
+}
+
+impl fmt::Display for Peer {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Peer::Ip(ip) => write!(f, "ip {}", ip),
+			Peer::Uid(uid) => write!(f, "uid {}", uid),
+			Peer::Unknown => write!(f, "unknown")
+		}
+	}
+}
+
+/** Something that can be banned or allowed */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccessEntry {
+	Name(String),
+	Ip(IpAddr),
+	Uid(u32)
+}
+
+impl fmt::Display for AccessEntry {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			AccessEntry::Name(name) => write!(f, "{}", name),
+			AccessEntry::Ip(ip) => write!(f, "ip {}", ip),
+			AccessEntry::Uid(uid) => write!(f, "uid {}", uid)
+		}
+	}
+}
+
+/** A player name, or `ip <address>` or `uid <number>` */
+impl FromStr for AccessEntry {
+	type Err = AnyError;
+	fn from_str(s: &str) -> Result<Self> {
+		let mut words = s.split_whitespace();
+		match (words.next(), words.next(), words.next()) {
+			(Some("ip"), Some(ip), None) => Ok(Self::Ip(ip.parse().map_err(|_| aerr!("'{}' is not an ip address", ip))?)),
+			(Some("uid"), Some(uid), None) => Ok(Self::Uid(uid.parse().map_err(|_| aerr!("'{}' is not a uid", uid))?)),
+			(Some(name), None, None) => Ok(Self::Name(name.to_string())),
+			_ => Err(aerr!("expected a player name, 'ip <address>' or 'uid <number>'"))
+		}
+	}
+}
+
+/** Who may play on the server */
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccessList {
+	#[serde(default)]
+	pub banned_names: BTreeSet<String>,
+	#[serde(default)]
+	pub banned_ips: BTreeSet<IpAddr>,
+	#[serde(default)]
+	pub banned_uids: BTreeSet<u32>,
+	/** Only let the allowed names and uids in */
+	#[serde(default)]
+	pub allowlist: bool,
+	#[serde(default)]
+	pub allowed_names: BTreeSet<String>,
+	#[serde(default)]
+	pub allowed_uids: BTreeSet<u32>
+}
+
+impl AccessList {
+
+	/** Read the list from a file. A file that doesn't exist yet is an empty list */
+	pub fn load(path: &Path) -> Result<Self> {
+		match fs::read_to_string(path) {
+			Ok(text) => Ok(json5::from_str(&text).map_err(|err| aerr!("access file {} is invalid: {}", path.display(), err))?),
+			Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+			Err(err) => Err(aerr!("can't read access file {}: {}", path.display(), err))
+		}
+	}
+
+	pub fn save(&self, path: &Path) -> Result<()> {
+		write_file_safe(path, serde_json::to_string_pretty(self)?)
+	}
+
+	/** Whether a new connection from here may go on to choose a name */
+	pub fn allows_peer(&self, peer: &Peer) -> bool {
+		match peer {
+			Peer::Ip(ip) => !self.banned_ips.contains(ip),
+			Peer::Uid(uid) => !self.banned_uids.contains(uid),
+			Peer::Unknown => true
+		}
+	}
+
+	/** Why a player can't join with this name from here, if they can't */
+	pub fn refusal(&self, name: &str, peer: &Peer) -> Option<&'static str> {
+		if self.banned_names.contains(name) || !self.allows_peer(peer) {
+			return Some("You are banned from this server");
+		}
+		let allowed_uid = matches!(peer, Peer::Uid(uid) if self.allowed_uids.contains(uid));
+		if self.allowlist && !self.allowed_names.contains(name) && !allowed_uid {
+			return Some("This server only lets invited players in");
+		}
+		None
+	}
+
+	/** Returns whether it wasn't banned already */
+	pub fn ban(&mut self, entry: AccessEntry) -> bool {
+		match entry {
+			AccessEntry::Name(name) => self.banned_names.insert(name),
+			AccessEntry::Ip(ip) => self.banned_ips.insert(ip),
+			AccessEntry::Uid(uid) => self.banned_uids.insert(uid)
+		}
+	}
+
+	/** Returns whether it was banned */
+	pub fn unban(&mut self, entry: &AccessEntry) -> bool {
+		match entry {
+			AccessEntry::Name(name) => self.banned_names.remove(name),
+			AccessEntry::Ip(ip) => self.banned_ips.remove(ip),
+			AccessEntry::Uid(uid) => self.banned_uids.remove(uid)
+		}
+	}
+
+	/** Let a name or uid in while the allowlist is on. Returns whether it wasn't allowed already */
+	pub fn allow(&mut self, entry: AccessEntry) -> Result<bool> {
+		match entry {
+			AccessEntry::Name(name) => Ok(self.allowed_names.insert(name)),
+			AccessEntry::Uid(uid) => Ok(self.allowed_uids.insert(uid)),
+			AccessEntry::Ip(_) => Err(aerr!("the allowlist is for names and uids"))
+		}
+	}
+
+	/** Returns whether it was allowed */
+	pub fn disallow(&mut self, entry: &AccessEntry) -> bool {
+		match entry {
+			AccessEntry::Name(name) => self.allowed_names.remove(name),
+			AccessEntry::Uid(uid) => self.allowed_uids.remove(uid),
+			AccessEntry::Ip(_) => false
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_bans_and_allowlist() {
+		let mut access = AccessList::default();
+		let home = Peer::Ip("10.0.0.2".parse().unwrap());
+		assert_eq!(access.refusal("alice", &home), None);
+		assert!(access.ban("ip 10.0.0.2".parse().unwrap()));
+		assert!(!access.ban("ip 10.0.0.2".parse().unwrap()));
+		assert!(!access.allows_peer(&home));
+		assert!(access.refusal("alice", &home).is_some());
+		assert!(access.unban(&"ip 10.0.0.2".parse().unwrap()));
+		access.ban("bob".parse().unwrap());
+		assert!(access.refusal("bob", &Peer::Unknown).is_some());
+
+		access.allowlist = true;
+		access.allow("carol".parse().unwrap()).unwrap();
+		access.allow("uid 1000".parse().unwrap()).unwrap();
+		assert!(access.allow("ip 10.0.0.2".parse().unwrap()).is_err());
+		assert_eq!(access.refusal("carol", &home), None);
+		assert_eq!(access.refusal("dave", &Peer::Uid(1000)), None);
+		assert_eq!(access.refusal("dave", &home), Some("This server only lets invited players in"));
+
+		let saved: AccessList = json5::from_str(&serde_json::to_string_pretty(&access).unwrap()).unwrap();
+		assert_eq!(saved, access);
+		assert_eq!(json5::from_str::<AccessList>("{banned_names: [\"eve\"]}").unwrap().banned_names.len(), 1);
+		assert!("uid x".parse::<AccessEntry>().is_err());
+		assert!("two names".parse::<AccessEntry>().is_err());
+	}
+}
@@ -0,0 +1,271 @@
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Sender, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use mio::{Ready, SetReadiness};
+use argon2::{
+	Argon2,
+	Algorithm,
+	Version,
+	Params,
+	password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString}
+};
+use crate::{
+	Result,
+	aerr,
+	access::Peer,
+	util::write_file_safe
+};
+
+const MIN_PASSWORD_LENGTH: usize = 6;
+/** Logging in happens during a game step, so hashing should stay fast enough not to hold it up */
+const HASH_MEMORY_KIB: u32 = 8 * 1024;
+const HASH_ITERATIONS: u32 = 2;
+/** Wrong passwords that can be tried before logging in has to wait */
+const FREE_ATTEMPTS: u32 = 3;
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/** The names that are protected by a password, with salted hashes of their passwords */
+#[derive(Debug, Clone, Default)]
+pub struct Accounts {
+	passwords: BTreeMap<String, String>,
+	file: Option<PathBuf>
+}
+
+impl Accounts {
+
+	/** Read the accounts from a file, which is written again whenever a password changes. Without a file the accounts are forgotten when the server stops */
+	pub fn open(file: Option<PathBuf>) -> Result<Self> {
+		let passwords = match &file {
+			Some(path) => match fs::read_to_string(path) {
+				Ok(text) => json5::from_str(&text).map_err(|err| aerr!("password file {} is invalid: {}", path.display(), err))?,
+				Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+				Err(err) => return Err(aerr!("can't read password file {}: {}", path.display(), err))
+			},
+			None => BTreeMap::new()
+		};
+		Ok(Self {passwords, file})
+	}
+
+	pub fn is_registered(&self, name: &str) -> bool {
+		self.passwords.contains_key(name)
+	}
+
+	pub fn verify(&self, name: &str, password: &str) -> bool {
+		self.password_hash(name).is_some_and(|hash| check_password(hash, password))
+	}
+
+	pub fn password_hash(&self, name: &str) -> Option<&str> {
+		self.passwords.get(name).map(String::as_str)
+	}
+
+	/** Set the password of a name, registering it if it wasn't */
+	pub fn register(&mut self, name: &str, password: &str) -> Result<()> {
+		if password.chars().count() < MIN_PASSWORD_LENGTH {
+			return Err(aerr!("a password needs at least {} characters", MIN_PASSWORD_LENGTH));
+		}
+		let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>()).map_err(|err| aerr!("can't make a salt: {}", err))?;
+		let params = Params::new(HASH_MEMORY_KIB, HASH_ITERATIONS, 1, None).map_err(|err| aerr!("invalid hash parameters: {}", err))?;
+		let hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+			.hash_password(password.as_bytes(), &salt)
+			.map_err(|err| aerr!("can't hash the password: {}", err))?;
+		self.passwords.insert(name.to_string(), hash.to_string());
+		self.save()
+	}
+
+	/** Take away the password of a name, so anyone can use it again. Returns whether it had one */
+	pub fn unregister(&mut self, name: &str) -> Result<bool> {
+		if self.passwords.remove(name).is_none() {
+			return Ok(false);
+		}
+		self.save()?;
+		Ok(true)
+	}
+
+	fn save(&self) -> Result<()> {
+		match &self.file {
+			Some(path) => write_file_safe(path, serde_json::to_string_pretty(&self.passwords)?),
+			None => Ok(())
+		}
+	}
+}
+
+fn check_password(hash: &str, password: &str) -> bool {
+	PasswordHash::new(hash)
+		.is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+}
+
+/** Checks passwords on a thread of its own, so that hashing doesn't hold up the game */
+pub struct PasswordChecker {
+	requests: Sender<(u64, String, String)>,
+	results: Receiver<(u64, bool)>
+}
+
+impl PasswordChecker {
+
+	/** The waker interrupts the game loop when a check is done */
+	pub fn start(waker: SetReadiness) -> Self {
+		let (requests, incoming) = channel::<(u64, String, String)>();
+		let (outgoing, results) = channel();
+		thread::spawn(move || {
+			for (ticket, hash, password) in incoming {
+				if outgoing.send((ticket, check_password(&hash, &password))).is_err() {
+					return;
+				}
+				let _ = waker.set_readiness(Ready::readable());
+			}
+		});
+		Self {requests, results}
+	}
+
+	/** Check a password against a hash. The result comes back with the same ticket */
+	pub fn check(&self, ticket: u64, hash: &str, password: String) {
+		let _ = self.requests.send((ticket, hash.to_string(), password));
+	}
+
+	/** The tickets of the checks that are done, with whether the password was right */
+	pub fn results(&self) -> Vec<(u64, bool)> {
+		self.results.try_iter().collect()
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Attempter {
+	Name(String),
+	Peer(Peer)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Failures {
+	count: u32,
+	until: Instant
+}
+
+/** Wrong passwords per name and per peer. Every wrong password after the first few doubles the time until the next try, so guessing is slow and doesn't keep the game busy with hashing */
+#[derive(Debug, Default)]
+pub struct LoginAttempts {
+	failures: HashMap<Attempter, Failures>
+}
+
+impl LoginAttempts {
+
+	fn attempters(name: &str, peer: Peer) -> Vec<Attempter> {
+		let mut attempters = vec![Attempter::Name(name.to_string())];
+		// connections from unknown places can't be told apart
+		if peer != Peer::Unknown {
+			attempters.push(Attempter::Peer(peer));
+		}
+		attempters
+	}
+
+	/** How long this name or peer has to wait before trying a password again */
+	pub fn backoff(&self, name: &str, peer: Peer, now: Instant) -> Option<Duration> {
+		Self::attempters(name, peer).iter()
+			.filter_map(|attempter| self.failures.get(attempter))
+			.map(|failures| failures.until.saturating_duration_since(now))
+			.max()
+			.filter(|wait| !wait.is_zero())
+	}
+
+	/** Remember a wrong password. Returns how many wrong passwords were given for this name in a row */
+	pub fn fail(&mut self, name: &str, peer: Peer, now: Instant) -> u32 {
+		// forget the ones that haven't tried for a while
+		self.failures.retain(|_, failures| now.saturating_duration_since(failures.until) < MAX_BACKOFF);
+		for attempter in Self::attempters(name, peer) {
+			let failures = self.failures.entry(attempter).or_insert(Failures {count: 0, until: now});
+			failures.count += 1;
+			failures.until = now + if failures.count <= FREE_ATTEMPTS {
+				Duration::ZERO
+			} else {
+				(Duration::from_secs(1) * 2u32.pow((failures.count - FREE_ATTEMPTS - 1).min(16))).min(MAX_BACKOFF)
+			};
+		}
+		self.failures[&Attempter::Name(name.to_string())].count
+	}
+
+	pub fn succeed(&mut self, name: &str, peer: Peer) {
+		for attempter in Self::attempters(name, peer) {
+			self.failures.remove(&attempter);
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_passwords() {
+		let path = std::env::temp_dir().join(format!("battilde-passwords-{}.json", std::process::id()));
+		let mut accounts = Accounts::open(Some(path.clone())).unwrap();
+		assert!(!accounts.is_registered("alice"));
+		assert!(accounts.register("alice", "short").is_err());
+		accounts.register("alice", "correct horse").unwrap();
+		assert!(accounts.verify("alice", "correct horse"));
+		assert!(!accounts.verify("alice", "wrong horse"));
+		assert!(!accounts.verify("bob", "correct horse"));
+		let text = fs::read_to_string(&path).unwrap();
+		assert!(text.contains("$argon2id$") && !text.contains("correct horse"), "{}", text);
+
+		let mut accounts = Accounts::open(Some(path.clone())).unwrap();
+		assert!(accounts.verify("alice", "correct horse"));
+		// the same password gets another salt
+		accounts.register("bob", "correct horse").unwrap();
+		assert_ne!(accounts.passwords["alice"], accounts.passwords["bob"]);
+		assert!(accounts.unregister("alice").unwrap());
+		assert!(!accounts.unregister("alice").unwrap());
+		assert!(!Accounts::open(Some(path.clone())).unwrap().is_registered("alice"));
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn test_passwords_are_checked_on_another_thread() {
+		let mut accounts = Accounts::default();
+		accounts.register("alice", "correct horse").unwrap();
+		let (_registration, waker) = mio::Registration::new2();
+		let checker = PasswordChecker::start(waker);
+		let hash = accounts.password_hash("alice").unwrap();
+		checker.check(1, hash, "wrong horse".to_string());
+		checker.check(2, hash, "correct horse".to_string());
+		let mut results = Vec::new();
+		for _ in 0..500 {
+			results.extend(checker.results());
+			if results.len() == 2 {
+				break;
+			}
+			thread::sleep(Duration::from_millis(10));
+		}
+		assert_eq!(results, vec![(1, false), (2, true)]);
+	}
+
+	#[test]
+	fn test_wrong_passwords_back_off() {
+		let mut attempts = LoginAttempts::default();
+		let start = Instant::now();
+		let home: Peer = Peer::Ip("10.0.0.1".parse().unwrap());
+		let away: Peer = Peer::Ip("10.0.0.2".parse().unwrap());
+		for count in 1..=FREE_ATTEMPTS {
+			assert_eq!(attempts.backoff("alice", home, start), None);
+			assert_eq!(attempts.fail("alice", home, start), count);
+		}
+		assert_eq!(attempts.fail("alice", home, start), FREE_ATTEMPTS + 1);
+		assert_eq!(attempts.backoff("alice", home, start), Some(Duration::from_secs(1)));
+		// another peer can't guess the same name, and this peer can't guess another name
+		assert!(attempts.backoff("alice", away, start).is_some());
+		assert!(attempts.backoff("bob", home, start).is_some());
+		assert_eq!(attempts.backoff("bob", away, start), None);
+		let later = start + Duration::from_secs(1);
+		assert_eq!(attempts.backoff("alice", home, later), None);
+		attempts.fail("alice", home, later);
+		assert_eq!(attempts.backoff("alice", home, later), Some(Duration::from_secs(2)));
+		attempts.succeed("alice", home);
+		assert_eq!(attempts.backoff("alice", home, later), None);
+		assert_eq!(attempts.fail("alice", Peer::Unknown, later), 1);
+		assert_eq!(attempts.backoff("carol", Peer::Unknown, later), None);
+	}
+}
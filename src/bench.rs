@@ -0,0 +1,68 @@
+
+use std::time::{Duration, Instant};
+
+use crate::{
+	PlayerId,
+	Sprite,
+	world::World,
+	worldmessages::MessageCache,
+	mapgen::load_map,
+	config::BenchConfig,
+	util::seed_rng
+};
+
+
+pub fn run_bench(config: BenchConfig) {
+	seed_rng(config.seed);
+	let map = load_map(config.map, config.custom_map.as_deref()).unwrap_or_else(|err| {
+		println!("{}", err);
+		std::process::exit(1);
+	});
+	let mut world = World::new(config.game_mode, map);
+	// a player that doesn't do anything, so that there is someone to draw the world for
+	let spectator = PlayerId("spectator".to_string());
+	world.add_player(&spectator, Sprite::Player("a", 's')).expect("can't add spectator");
+	world.set_min_players(config.bots + 1);
+	
+	let mut message_cache = MessageCache::default();
+	let mut view_time = Duration::from_millis(0);
+	let mut bytes = 0;
+	let mut steps: u64 = 0;
+	let start = Instant::now();
+	while steps < config.max_steps && world.wave() < config.waves && !world.is_round_over() {
+		world.update();
+		// nothing logs the events here
+		world.take_events();
+		let view_start = Instant::now();
+		for (player, mut message) in world.view() {
+			message_cache.trim(&player, &mut message);
+			if !message.is_empty() {
+				bytes += message.to_json().to_string().len();
+			}
+		}
+		view_time += view_start.elapsed();
+		steps += 1;
+	}
+	let total = start.elapsed();
+	
+	println!("ran {} steps in {:.3}s ({:.1} steps/s)", steps, total.as_secs_f64(), steps as f64 / total.as_secs_f64());
+	println!("reached wave {}{}", world.wave(), if world.is_round_over() {" (game over)"} else {""});
+	for (name, score) in world.scores() {
+		println!("{}: {} points", name, score);
+	}
+	let phases = world.phase_times();
+	for (name, time) in [
+			("update_creatures", phases.update_creatures),
+			("update_bullets", phases.update_bullets),
+			("spawn", phases.spawn),
+			("view", view_time)] {
+		println!(
+			"{:<17} {:>9.3}s {:>9.1}µs/step {:>5.1}%",
+			name,
+			time.as_secs_f64(),
+			time.as_secs_f64() * 1e6 / steps.max(1) as f64,
+			100.0 * time.as_secs_f64() / total.as_secs_f64()
+		);
+	}
+	println!("{} bytes of messages", bytes);
+}
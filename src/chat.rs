@@ -0,0 +1,64 @@
+
+use std::collections::VecDeque;
+
+pub const MAX_CHAT_LENGTH: usize = 400;
+const HISTORY_LENGTH: usize = 20;
+
+/** Make chat text safe to show in a terminal: whitespace becomes plain spaces and other control characters are dropped */
+pub fn sanitize(text: &str) -> String {
+	text.chars()
+		.map(|c| if c.is_whitespace() {' '} else {c})
+		.filter(|c| !c.is_control())
+		.collect::<String>()
+		.trim()
+		.to_string()
+}
+
+/** The last public chat lines, for players who join later */
+#[derive(Debug, Clone, Default)]
+pub struct ChatHistory {
+	lines: VecDeque<String>
+}
+
+impl ChatHistory {
+
+	pub fn push(&mut self, line: String) {
+		if self.lines.len() >= HISTORY_LENGTH {
+			self.lines.pop_front();
+		}
+		self.lines.push_back(line);
+	}
+
+	/** Oldest first */
+	pub fn lines(&self) -> impl Iterator<Item=&String> {
+		self.lines.iter()
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_sanitize() {
+		assert_eq!(sanitize("hello"), "hello");
+		assert_eq!(sanitize("\x1b[2Jgone\x07"), "[2Jgone");
+		assert_eq!(sanitize(" two\nlines\t "), "two lines");
+		assert_eq!(sanitize("\u{9b}31mred"), "31mred");
+		assert_eq!(sanitize("héllo 👋"), "héllo 👋");
+		assert_eq!(sanitize("\x00\r\n"), "");
+	}
+
+	#[test]
+	fn test_history_keeps_the_last_lines() {
+		let mut history = ChatHistory::default();
+		for i in 0..HISTORY_LENGTH + 5 {
+			history.push(i.to_string());
+		}
+		let lines: Vec<&String> = history.lines().collect();
+		assert_eq!(lines.len(), HISTORY_LENGTH);
+		assert_eq!(lines[0], "5");
+		assert_eq!(lines[HISTORY_LENGTH - 1], &(HISTORY_LENGTH + 4).to_string());
+	}
+}
@@ -1,8 +1,14 @@
 
 use structopt::StructOpt;
+use std::ffi::OsString;
+use std::fs;
+use std::net::SocketAddr;
 use std::path::PathBuf;
+use serde::Deserialize;
 use crate::{
 	Address,
+	Result,
+	aerr,
 	gamemode::GameMode,
 	mapgen::BuiltinMap
 };
@@ -14,20 +20,220 @@ pub struct Config {
 	#[structopt(short, long, help="A server type and address. Allowed server types: 'inet', 'unix', 'abstract'. Example: \"inet:127.0.0.1:1234\" or \"abstract:battilde\" or \"unix:/tmp/battilde\" or \"inet:[::1]:1234\"")]
 	pub address: Option<Vec<Address>>,
 	
-	#[structopt(long, env="USER", help="The name(s) of the server admin(s)")]
+	#[structopt(long, env="USER", default_value="", help="The name(s) of the server admin(s)")]
 	pub admins: String,
 	
+	#[structopt(long, default_value="", help="The name(s) of the players that may change maps in the editor gamemode, besides the admins")]
+	pub builders: String,
+	
 	#[structopt(long, default_value="100", help="The time (in milliseconds) between two steps")]
 	pub step_duration: u64,
 	
-	#[structopt(long, default_value="survival", help="The gamemode of the server. Options: survival, pillars, pvp")]
+	#[structopt(long, default_value="survival", help="The gamemode of the server. Options: survival, pillars, control, escort, pvp, teams, ctf, koth, editor")]
 	pub game_mode: GameMode,
 	
-	#[structopt(long, default_value="square", help="The built-in map to play. Ignored if --custom-map is used.")]
+	#[structopt(long, default_value="2", help="The number of teams in the teams, ctf and koth gamemodes")]
+	pub teams: usize,
+	
+	#[structopt(long, help="End a pvp round when a player or team reaches this score. 0 for no limit. Defaults to 20 kills, 3 captured flags or 1000 steps of holding zones depending on the gamemode")]
+	pub score_limit: Option<u64>,
+	
+	#[structopt(long, default_value="600", help="End a pvp round after this many seconds. 0 for no limit")]
+	pub round_time: u64,
+	
+	#[structopt(long, help="Win survival, pillars and control rounds after surviving this many waves. Defaults to what the map says, which is usually never")]
+	pub survive_waves: Option<usize>,
+	
+	#[structopt(long, default_value="square", help="The built-in map to play: square, cave or dungeon. Caves and dungeons take a size, like cave:96. Ignored if --custom-map is used.")]
 	pub map: BuiltinMap,
 	
-	#[structopt(long, help="File path for a custom map to play")]
+	#[structopt(long, help="File path for a custom map to play. Give this multiple times to play the maps in turn, moving on after every round")]
+	pub custom_map: Vec<PathBuf>,
+	
+	#[structopt(long, help="A directory with more custom maps (.json or .json5) to add to the rotation. Maps from the editor are saved here")]
+	pub map_dir: Option<PathBuf>,
+	
+	#[structopt(long, default_value="0", help="Fill empty slots with bots until there are this many players. Bots only play while someone is connected")]
+	pub bots: usize,
+	
+	#[structopt(long, help="Append the event log to this file instead of writing it to standard output. Every line is a JSON object with the time, level and event")]
+	pub event_log: Option<PathBuf>,
+	
+	#[structopt(long, help="Serve metrics for monitoring in the Prometheus text format over HTTP on this address, like 127.0.0.1:9222")]
+	pub metrics_address: Option<SocketAddr>,
+	
+	#[structopt(long, help="Also accept operator commands on this unix socket, besides standard input")]
+	pub control_socket: Option<PathBuf>,
+	
+	#[structopt(long, help="A file that keeps the banned players and the allowlist across restarts. It is created when something is banned or allowed")]
+	pub access_file: Option<PathBuf>,
+	
+	#[structopt(long, help="A file with the salted password hashes of registered names. Without it passwords are forgotten when the server stops")]
+	pub password_file: Option<PathBuf>,
+	
+	#[structopt(long, help="A JSON5 file with values for the other options, like {step_duration: 150, admins: \"alice,bob\"}. Options given on the command line take precedence")]
+	pub config: Option<PathBuf>,
+	
+	#[structopt(subcommand)]
+	pub command: Option<Command>,
+	
+}
+
+impl Config {
+	
+	/** The command line arguments, completed with the config file if one is given */
+	pub fn load() -> Result<Self> {
+		Self::load_from(std::env::args_os())
+	}
+	
+	pub fn load_from<I: IntoIterator<Item=OsString>>(args: I) -> Result<Self> {
+		let matches = Self::clap().get_matches_from(args);
+		let mut config = Self::from_clap(&matches);
+		if let Some(path) = config.config.clone() {
+			let text = fs::read_to_string(&path)
+				.map_err(|err| aerr!("can't read config file {}: {}", path.display(), err))?;
+			let file: ConfigFile = json5::from_str(&text)
+				.map_err(|err| aerr!("config file {} is invalid: {}", path.display(), err))?;
+			config.complete(file, |flag| matches.occurrences_of(flag) > 0)
+				.map_err(|err| aerr!("config file {}: {}", path.display(), err))?;
+		}
+		Ok(config)
+	}
+	
+	/** Take the values from the config file for the options that were not given on the command line */
+	fn complete(&mut self, file: ConfigFile, given: impl Fn(&str) -> bool) -> Result<()> {
+		let use_file = |field: &str| !given(&field.replace('_', "-"));
+		macro_rules! take {
+			($($field:ident),*) => {$(
+				if let Some(value) = file.$field.filter(|_| use_file(stringify!($field))) {
+					self.$field = value.into();
+				}
+			)*}
+		}
+		take!(admins, builders, step_duration, teams, score_limit, round_time, survive_waves, custom_map, map_dir, bots, event_log, metrics_address, control_socket, access_file, password_file);
+		if let Some(address) = file.address.filter(|_| use_file("address")) {
+			self.address = Some(address.iter().map(|address| address.parse()).collect::<Result<_>>()?);
+		}
+		if let Some(game_mode) = file.game_mode.filter(|_| use_file("game_mode")) {
+			self.game_mode = game_mode.parse()?;
+		}
+		if let Some(map) = file.map.filter(|_| use_file("map")) {
+			self.map = map.parse()?;
+		}
+		Ok(())
+	}
+}
+
+/** The server options as they are written in a config file, with underscores instead of dashes */
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+	address: Option<Vec<String>>,
+	admins: Option<String>,
+	builders: Option<String>,
+	step_duration: Option<u64>,
+	game_mode: Option<String>,
+	teams: Option<usize>,
+	score_limit: Option<u64>,
+	round_time: Option<u64>,
+	survive_waves: Option<usize>,
+	map: Option<String>,
+	custom_map: Option<Vec<PathBuf>>,
+	map_dir: Option<PathBuf>,
+	bots: Option<usize>,
+	event_log: Option<PathBuf>,
+	metrics_address: Option<SocketAddr>,
+	control_socket: Option<PathBuf>,
+	access_file: Option<PathBuf>,
+	password_file: Option<PathBuf>
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+	#[structopt(about="Run the game without network, with only bots playing, and report how fast it runs")]
+	Bench(BenchConfig),
+	#[structopt(about="Check a custom map file for mistakes and show which gamemodes it can be played in")]
+	CheckMap(CheckMapConfig)
+}
+
+#[derive(Debug, StructOpt)]
+pub struct BenchConfig {
+	
+	#[structopt(long, default_value="0", help="Seed for the random generator")]
+	pub seed: u64,
+	
+	#[structopt(long, default_value="survival", help="The gamemode to simulate. Options: survival, pillars, control, escort, pvp, teams, ctf, koth")]
+	pub game_mode: GameMode,
+	
+	#[structopt(long, default_value="square", help="The built-in map to simulate: square, cave or dungeon. Caves and dungeons take a size, like cave:96. Ignored if --custom-map is used.")]
+	pub map: BuiltinMap,
+	
+	#[structopt(long, help="File path for a custom map to simulate")]
 	pub custom_map: Option<PathBuf>,
 	
+	#[structopt(long, default_value="10", help="Stop when this wave is reached")]
+	pub waves: usize,
+	
+	#[structopt(long, default_value="4", help="The number of bots that play")]
+	pub bots: usize,
+	
+	#[structopt(long, default_value="100000", help="Stop after this many steps")]
+	pub max_steps: u64,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct CheckMapConfig {
+	
+	#[structopt(help="The map file to check")]
+	pub file: PathBuf,
+	
+	#[structopt(long, help="Only check whether the map can be played in this gamemode")]
+	pub game_mode: Option<GameMode>,
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn load(file: &str, args: &[&str]) -> Result<Config> {
+		let path = std::env::temp_dir().join(format!("battilde-config-{}.json5", std::process::id()));
+		fs::write(&path, file).unwrap();
+		let config = Config::load_from(
+			["battilde", "--config", path.to_str().unwrap()].iter()
+				.chain(args)
+				.map(OsString::from)
+		);
+		fs::remove_file(&path).unwrap();
+		config
+	}
 	
+	#[test]
+	fn test_command_line_overrides_config_file() {
+		let file = r#"{
+			// comments are allowed
+			address: ["inet:127.0.0.1:1234"],
+			admins: "alice,bob",
+			step_duration: 150,
+			game_mode: "ctf",
+			map: "cave:80",
+			score_limit: 5,
+			bots: 3,
+		}"#;
+		let config = load(file, &[]).unwrap();
+		assert_eq!(config.admins, "alice,bob");
+		assert_eq!(config.step_duration, 150);
+		assert_eq!(config.game_mode, GameMode::CaptureTheFlag);
+		assert_eq!(config.score_limit, Some(5));
+		assert_eq!(config.bots, 3);
+		assert_eq!(config.address.unwrap().len(), 1);
+		assert_eq!(config.teams, 2);
+		let config = load(file, &["--step-duration", "80", "--game-mode", "teams", "--bots=0"]).unwrap();
+		assert_eq!(config.step_duration, 80);
+		assert_eq!(config.game_mode, GameMode::Teams);
+		assert_eq!(config.bots, 0);
+		assert_eq!(config.admins, "alice,bob");
+		assert!(load("{step_time: 150}", &["--bots=1"]).is_err());
+		assert!(load("{game_mode: \"chess\"}", &["--bots=1", "--teams=2"]).is_err());
+	}
 }
@@ -0,0 +1,219 @@
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::mpsc::{channel, Sender, Receiver};
+use std::thread;
+use mio::{Ready, SetReadiness};
+use crate::{
+	PlayerId,
+	Result,
+	aerr,
+	errors::AnyError,
+	gamemode::GameMode,
+	access::AccessEntry
+};
+
+pub const CONSOLE_HELP: &str = "Commands: players, kick <name> [reason], ban <name|ip <address>|uid <number>>, unban <...>, allow <name|uid <number>>, disallow <...>, allowlist on|off, register <name> <password>, unregister <name>, say <text>, reset, map <name>, mode <gamemode>, step <milliseconds>, save <name>, help";
+
+/** What the operator of the server can do */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsoleCommand {
+	Players,
+	Kick(PlayerId, Option<String>),
+	Ban(AccessEntry),
+	Unban(AccessEntry),
+	Allow(AccessEntry),
+	Disallow(AccessEntry),
+	Allowlist(bool),
+	Register(PlayerId, String),
+	Unregister(PlayerId),
+	Say(String),
+	Reset,
+	Map(String),
+	Mode(GameMode),
+	StepDuration(u64),
+	Save(String),
+	Help
+}
+
+impl FromStr for ConsoleCommand {
+	type Err = AnyError;
+	fn from_str(s: &str) -> Result<Self> {
+		let s = s.trim();
+		let (command, arg) = match s.find(char::is_whitespace) {
+			Some(index) => (&s[..index], Some(s[index..].trim())),
+			None => (s, None)
+		};
+		let arg = arg.filter(|arg| !arg.is_empty());
+		let needs = |what: &str| arg.ok_or_else(|| aerr!("{} needs {}", command, what));
+		Ok(match command {
+			"players" => Self::Players,
+			"kick" => {
+				let arg = needs("a player name")?;
+				let (name, reason) = match arg.find(char::is_whitespace) {
+					Some(index) => (&arg[..index], Some(arg[index..].trim().to_string())),
+					None => (arg, None)
+				};
+				Self::Kick(PlayerId(name.to_string()), reason)
+			}
+			"ban" => Self::Ban(needs("a player name")?.parse()?),
+			"unban" => Self::Unban(needs("a player name")?.parse()?),
+			"allow" => Self::Allow(needs("a player name")?.parse()?),
+			"disallow" => Self::Disallow(needs("a player name")?.parse()?),
+			"allowlist" => Self::Allowlist(match needs("on or off")? {
+				"on" => true,
+				"off" => false,
+				other => return Err(aerr!("allowlist takes on or off, not '{}'", other))
+			}),
+			"register" => {
+				let mut words = needs("a player name and a password")?.split_whitespace();
+				match (words.next(), words.next(), words.next()) {
+					(Some(name), Some(password), None) => Self::Register(PlayerId(name.to_string()), password.to_string()),
+					_ => return Err(aerr!("register needs a player name and a password without spaces"))
+				}
+			}
+			"unregister" => Self::Unregister(PlayerId(needs("a player name")?.to_string())),
+			"say" => Self::Say(needs("a message")?.to_string()),
+			"reset" => Self::Reset,
+			"map" => Self::Map(needs("a map name")?.to_string()),
+			"mode" => Self::Mode(needs("a gamemode")?.parse()?),
+			"step" => Self::StepDuration(
+				needs("a duration in milliseconds")?.parse()
+					.map_err(|_| aerr!("the step duration must be a whole number of milliseconds"))?
+			),
+			"save" => Self::Save(needs("a map name")?.to_string()),
+			"help" => Self::Help,
+			_ => return Err(aerr!("unknown command '{}'. {}", command, CONSOLE_HELP))
+		})
+	}
+}
+
+/** The command as it can be written to the event log, without passwords */
+pub fn loggable_command(line: &str) -> String {
+	let words: Vec<&str> = line.split_whitespace().collect();
+	match words.as_slice() {
+		["register", name, ..] => format!("register {} ...", name),
+		_ => line.to_string()
+	}
+}
+
+/** A line from the operator, waiting for an answer */
+pub struct ConsoleRequest {
+	pub line: String,
+	reply: Sender<String>
+}
+
+impl ConsoleRequest {
+	pub fn reply(&self, text: &str) {
+		let _ = self.reply.send(text.to_string());
+	}
+}
+
+/** Reads commands from the operator in threads of its own, so the game doesn't wait for them */
+pub struct Console {
+	sender: Sender<ConsoleRequest>,
+	requests: Receiver<ConsoleRequest>,
+	waker: SetReadiness
+}
+
+impl Console {
+
+	/** The waker interrupts the game loop when a command comes in */
+	pub fn new(waker: SetReadiness) -> Self {
+		let (sender, requests) = channel();
+		Self {sender, requests, waker}
+	}
+
+	/** Read commands from standard input. Answers go to standard error, so they stay out of the event log */
+	pub fn read_stdin(&self) {
+		let sender = self.sender.clone();
+		let waker = self.waker.clone();
+		thread::spawn(move || serve_lines(io::stdin().lock(), io::stderr(), &sender, &waker));
+	}
+
+	/** Accept commands on a unix socket, one per line. A socket left behind by an earlier run is replaced */
+	pub fn listen(&self, path: &Path) -> Result<()> {
+		if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+			fs::remove_file(path)?;
+		}
+		let listener = UnixListener::bind(path).map_err(|err| aerr!("can't open control socket {}: {}", path.display(), err))?;
+		let sender = self.sender.clone();
+		let waker = self.waker.clone();
+		thread::spawn(move || {
+			for stream in listener.incoming().flatten() {
+				let sender = sender.clone();
+				let waker = waker.clone();
+				thread::spawn(move || {
+					if let Ok(input) = stream.try_clone() {
+						serve_lines(BufReader::new(input), stream, &sender, &waker);
+					}
+				});
+			}
+		});
+		Ok(())
+	}
+
+	/** The commands that came in since the last call */
+	pub fn requests(&self) -> Vec<ConsoleRequest> {
+		self.requests.try_iter().collect()
+	}
+}
+
+fn serve_lines(input: impl BufRead, mut output: impl Write, sender: &Sender<ConsoleRequest>, waker: &SetReadiness) {
+	for line in input.lines() {
+		let Ok(line) = line else {
+			return;
+		};
+		if line.trim().is_empty() {
+			continue;
+		}
+		let (reply, answer) = channel();
+		if sender.send(ConsoleRequest {line, reply}).is_err() {
+			return;
+		}
+		let _ = waker.set_readiness(Ready::readable());
+		match answer.recv() {
+			Ok(text) => {
+				if writeln!(output, "{}", text).is_err() {
+					return;
+				}
+			}
+			Err(_) => return
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse_commands() {
+		assert_eq!("players".parse::<ConsoleCommand>().unwrap(), ConsoleCommand::Players);
+		assert_eq!(
+			" kick  bob  stop   spawn camping ".parse::<ConsoleCommand>().unwrap(),
+			ConsoleCommand::Kick(PlayerId("bob".to_string()), Some("stop   spawn camping".to_string()))
+		);
+		assert_eq!("kick bob".parse::<ConsoleCommand>().unwrap(), ConsoleCommand::Kick(PlayerId("bob".to_string()), None));
+		assert_eq!("say hello there".parse::<ConsoleCommand>().unwrap(), ConsoleCommand::Say("hello there".to_string()));
+		assert_eq!("mode ctf".parse::<ConsoleCommand>().unwrap(), ConsoleCommand::Mode(GameMode::CaptureTheFlag));
+		assert_eq!("step 80".parse::<ConsoleCommand>().unwrap(), ConsoleCommand::StepDuration(80));
+		assert!("step fast".parse::<ConsoleCommand>().is_err());
+		assert!("ban".parse::<ConsoleCommand>().unwrap_err().to_string().contains("ban needs a player name"));
+		assert_eq!("ban uid 1000".parse::<ConsoleCommand>().unwrap(), ConsoleCommand::Ban(AccessEntry::Uid(1000)));
+		assert_eq!("allowlist on".parse::<ConsoleCommand>().unwrap(), ConsoleCommand::Allowlist(true));
+		assert!("allowlist maybe".parse::<ConsoleCommand>().is_err());
+		assert!("shutdown".parse::<ConsoleCommand>().is_err());
+		assert_eq!(
+			"register alice hunter22".parse::<ConsoleCommand>().unwrap(),
+			ConsoleCommand::Register(PlayerId("alice".to_string()), "hunter22".to_string())
+		);
+		assert_eq!(loggable_command("register alice hunter22"), "register alice ...");
+		assert_eq!(loggable_command("kick alice"), "kick alice");
+	}
+}
@@ -1,7 +1,7 @@
 
 
 use serde::{Serialize, Deserialize};
-use crate::{PlayerId, Direction, Pos, sprite::Sprite};
+use crate::{PlayerId, Direction, Pos, sprite::Sprite, creature::CreatureType};
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,13 +12,48 @@ pub enum Control {
 	ShootPrecise(Pos),
 	Suicide,
 	NextWeapon,
-	PreviousWeapon
+	PreviousWeapon,
+	Team(usize),
+	/** Choose the map or gamemode of the next round */
+	Vote(String),
+	/** Start a new round with the maps as they are on disk now. Only for admins */
+	Reload,
+	/** Map editor: put the tile with this name under the cursor */
+	PlaceTile(String),
+	/** Map editor: put a creature under the cursor */
+	PlaceCreature(CreatureType),
+	/** Map editor: add or remove a monster spawn under the cursor */
+	ToggleMonsterSpawn,
+	/** Map editor: make the position of the cursor the spawnpoint */
+	SetSpawnpoint,
+	/** Map editor: remove creatures and monster spawns under the cursor */
+	Erase,
+	/** Map editor: write the map to a file with this name */
+	SaveMap(String)
+}
+
+impl Control {
+	pub fn is_admin_only(&self) -> bool {
+		matches!(self, Self::Reload)
+	}
+	
+	/** Whether this changes the map in the editor, which only admins and builders may do */
+	pub fn is_edit(&self) -> bool {
+		matches!(self, Self::PlaceTile(_) | Self::PlaceCreature(_) | Self::ToggleMonsterSpawn | Self::SetSpawnpoint | Self::Erase | Self::SaveMap(_))
+	}
+	
+	/** Whether the server handles this instead of the world */
+	pub fn is_server_command(&self) -> bool {
+		matches!(self, Self::Reload | Self::SaveMap(_))
+	}
 }
 
 #[derive(Debug, Clone)]
 pub enum Action {
 	Join(PlayerId, Sprite),
 	Leave(PlayerId),
-	Input(PlayerId, Control)
+	Input(PlayerId, Control),
+	/** A chat message for the team of the player, which only the world knows */
+	TeamChat(PlayerId, String)
 }
 
@@ -1,6 +1,6 @@
 
 use serde::{Serialize, Deserialize};
-use rand::{Rng, thread_rng};
+use rand::Rng;
 
 use crate::{
 	sprite::Sprite,
@@ -8,17 +8,20 @@ use crate::{
 	Direction,
 	weapon::Weapon,
 	PlayerId,
-	util::Percentage,
+	util::{Percentage, rng},
 	timestamp::Duration,
-	pos::Distance
+	pos::Distance,
+	item::Item
 };
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Mind {
 	Player(PlayerId),
+	Bot(PlayerId),
 	BloodThirst(Percentage),
 	Destroyer,
 	Pillar,
+	Payload,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -31,15 +34,15 @@ pub enum CreatureType {
 	Player,
 	Vargr,
 	Xiangliu,
-	Wasp
+	Wasp,
+	Payload
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Alignment {
-	#[allow(dead_code)]
 	Players,
-	#[allow(dead_code)]
 	Player(PlayerId),
+	Team(usize),
 	Monsters
 }
 
@@ -59,13 +62,21 @@ pub struct Creature {
 	pub alignment: Alignment,
 	pub weapons: Vec<Weapon>,
 	pub selected_weapon: usize,
-	pub is_building: bool
+	pub is_building: bool,
+	pub carried: Option<Item>
 }
 
 impl Creature {
 	
 	pub fn is_player(&self) -> bool {
-		matches!(self.mind, Mind::Player(_))
+		matches!(self.mind, Mind::Player(_) | Mind::Bot(_))
+	}
+	
+	pub fn player_id(&self) -> Option<&PlayerId> {
+		match &self.mind {
+			Mind::Player(playerid) | Mind::Bot(playerid) => Some(playerid),
+			_ => None
+		}
 	}
 	
 	pub fn is_dead(&self) -> bool {
@@ -80,7 +91,7 @@ impl Creature {
 		self.health.0 = self.health.0.max(self.max_health.0.min(self.health.0 + amount.0));
 	}
 	
-	pub fn has_full_health(&mut self) -> bool {
+	pub fn has_full_health(&self) -> bool {
 		self.health.0 >= self.max_health.0
 	}
 	
@@ -105,7 +116,7 @@ impl Creature {
 	
 	pub fn create_creature(typ: CreatureType, pos: Pos) -> Self{
 		match typ {
-			CreatureType::Player => Self::new_player(PlayerId("".to_string()), Sprite::new("player_g:X"), pos, true), // will probably commite suicide immediately
+			CreatureType::Player => Self::new_player(PlayerId(String::new()), Sprite::new("player_g:X"), pos, Alignment::Player(PlayerId(String::new()))), // will probably commite suicide immediately
 			CreatureType::Zombie => Self::new_zombie(pos),
 			CreatureType::Ymp => Self::new_ymp(pos),
 			CreatureType::Worm => Self::new_worm(pos),
@@ -113,11 +124,12 @@ impl Creature {
 			CreatureType::Pillar => Self::new_pillar(pos),
 			CreatureType::Xiangliu => Self::new_xiangliu(pos),
 			CreatureType::Vargr => Self::new_vargr(pos),
-			CreatureType::Wasp => Self::new_wasp(pos)
+			CreatureType::Wasp => Self::new_wasp(pos),
+			CreatureType::Payload => Self::new_payload(pos)
 		}
 	}
 	
-	pub fn new_player(playerid: PlayerId, sprite: Sprite, pos: Pos, pvp: bool) -> Self {
+	pub fn new_player(playerid: PlayerId, sprite: Sprite, pos: Pos, alignment: Alignment) -> Self {
 		Self {
 			mind: Mind::Player(playerid.clone()),
 			pos,
@@ -133,13 +145,16 @@ impl Creature {
 				Weapon::smg(),
 			],
 			selected_weapon: 0,
-			alignment: 
-				if pvp {
-					Alignment::Player(playerid)
-				} else {
-					Alignment::Players
-				},
-			is_building: false
+			alignment,
+			is_building: false,
+			carried: None
+		}
+	}
+	
+	pub fn new_bot(playerid: PlayerId, sprite: Sprite, pos: Pos, alignment: Alignment) -> Self {
+		Self {
+			mind: Mind::Bot(playerid.clone()),
+			..Self::new_player(playerid, sprite, pos, alignment)
 		}
 	}
 	
@@ -156,7 +171,27 @@ impl Creature {
 			weapons: vec![],
 			selected_weapon: 0,
 			alignment: Alignment::Players,
-			is_building: true
+			is_building: true,
+			carried: None
+		}
+	}
+	
+	/** A slow cart that players escort to the exit. Monsters go for it like for pillars */
+	pub fn new_payload(pos: Pos) -> Self {
+		Self {
+			mind: Mind::Payload,
+			pos,
+			dir: Direction::North,
+			health: Health(400),
+			max_health: Health(400),
+			cooldown: Duration(0),
+			walk_cooldown: Duration(4),
+			sprite: Sprite::new("payload"),
+			weapons: vec![],
+			selected_weapon: 0,
+			alignment: Alignment::Players,
+			is_building: true,
+			carried: None
 		}
 	}
 	
@@ -244,13 +279,14 @@ impl Creature {
 			dir: Direction::North,
 			health,
 			max_health: health,
-			cooldown: Duration(thread_rng().gen_range(0..=cooldown.0)),
+			cooldown: Duration(rng().gen_range(0..=cooldown.0)),
 			walk_cooldown: cooldown,
 			sprite,
 			weapons: vec![weapon],
 			selected_weapon: 0,
 			alignment: Alignment::Monsters,
-			is_building: false
+			is_building: false,
+			carried: None
 		}
 	}
 }
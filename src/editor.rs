@@ -0,0 +1,110 @@
+
+use std::collections::HashSet;
+use crate::{
+	Pos,
+	Result,
+	aerr,
+	sprite::Sprite,
+	controls::Control,
+	tile::Tile,
+	creature::{Creature, CreatureType},
+	mapgen::{MapTemplate, MonsterSpawn}
+};
+
+/** A map that players are building in the editor gamemode */
+#[derive(Debug, Clone)]
+pub struct Editor {
+	template: MapTemplate,
+	changed_ground: HashSet<Pos>
+}
+
+impl Editor {
+
+	pub fn new(template: MapTemplate) -> Self {
+		Self {template, changed_ground: HashSet::new()}
+	}
+
+	pub fn template(&self) -> &MapTemplate {
+		&self.template
+	}
+
+	/** Change the map at the cursor. Returns the ground at the cursor afterwards */
+	pub fn apply(&mut self, cursor: Pos, control: &Control) -> Result<Tile> {
+		let map = &mut self.template;
+		let tile = *map.ground.get(cursor).ok_or_else(|| aerr!("the cursor is outside the map"))?;
+		match control {
+			Control::PlaceTile(name) => {
+				let tile = Tile::from_name(name).ok_or_else(|| aerr!("'{}' is not a tile", name))?;
+				map.ground.set(cursor, tile);
+				self.changed_ground.insert(cursor);
+				return Ok(tile);
+			}
+			Control::PlaceCreature(CreatureType::Player) => return Err(aerr!("players can't be placed")),
+			Control::PlaceCreature(creature) => {
+				map.creatures.retain(|(pos, _)| *pos != cursor);
+				map.creatures.push((cursor, *creature));
+			}
+			Control::ToggleMonsterSpawn => {
+				if map.monsterspawn.iter().any(|spawn| spawn.pos == cursor) {
+					map.monsterspawn.retain(|spawn| spawn.pos != cursor);
+				} else {
+					map.monsterspawn.push(MonsterSpawn::new(cursor));
+				}
+			}
+			Control::SetSpawnpoint => map.spawnpoint = cursor,
+			Control::Erase => {
+				map.creatures.retain(|(pos, _)| *pos != cursor);
+				map.monsterspawn.retain(|spawn| spawn.pos != cursor);
+			}
+			_ => return Err(aerr!("{:?} is not an edit", control))
+		}
+		Ok(tile)
+	}
+
+	/** What is placed on the map, to be drawn over the ground */
+	pub fn sprites(&self) -> Vec<(Pos, Sprite)> {
+		let map = &self.template;
+		map.creatures.iter()
+			.map(|(pos, creature)| (*pos, Creature::create_creature(*creature, *pos).sprite))
+			.chain(map.monsterspawn.iter().map(|spawn| (spawn.pos, Sprite::new("monsterspawn"))))
+			.chain(Some((map.spawnpoint, Sprite::new("spawnpoint"))))
+			.collect()
+	}
+
+	/** Where the ground was changed, so it has to be drawn again */
+	pub fn changed_ground(&self) -> impl Iterator<Item=&Pos> {
+		self.changed_ground.iter()
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_edits() {
+		let mut editor = Editor::new(json5::from_str(r#"{
+			size: [3, 2],
+			spawnpoint: [0, 0],
+			monsterspawn: [],
+			ground: ["...", "..."],
+			creatures: []
+		}"#).unwrap());
+		let cursor = Pos::new(2, 1);
+		assert_eq!(editor.apply(cursor, &Control::PlaceTile("water".to_string())).unwrap(), Tile::from_name("water").unwrap());
+		assert!(editor.apply(cursor, &Control::PlaceTile("lava".to_string())).is_err());
+		assert!(editor.apply(Pos::new(3, 1), &Control::SetSpawnpoint).is_err());
+		editor.apply(cursor, &Control::PlaceCreature(CreatureType::Zombie)).unwrap();
+		editor.apply(cursor, &Control::PlaceCreature(CreatureType::Pillar)).unwrap();
+		editor.apply(cursor, &Control::ToggleMonsterSpawn).unwrap();
+		editor.apply(Pos::new(1, 0), &Control::SetSpawnpoint).unwrap();
+		assert_eq!(editor.template().creatures, vec![(cursor, CreatureType::Pillar)]);
+		assert_eq!(editor.template().monsterspawn, vec![MonsterSpawn::new(cursor)]);
+		assert_eq!(editor.template().spawnpoint, Pos::new(1, 0));
+		assert_eq!(editor.sprites().len(), 3);
+		editor.apply(cursor, &Control::Erase).unwrap();
+		assert!(editor.template().creatures.is_empty());
+		assert!(editor.template().monsterspawn.is_empty());
+	}
+}
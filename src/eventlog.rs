@@ -0,0 +1,134 @@
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Utc, SecondsFormat};
+use serde::Serialize;
+use serde_json::{Value, json};
+use crate::{
+	PlayerId,
+	Result,
+	aerr,
+	gamemode::GameMode
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all="lowercase")]
+pub enum Level {
+	Info,
+	Warning,
+	Error
+}
+
+/** Something that happened in the world that is worth logging */
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameEvent {
+	Death{player: PlayerId, cause: String},
+	WaveStart{wave: usize},
+	GameOver{won: bool, scores: Vec<(String, u64)>},
+	Reset{map: String, gamemode: GameMode}
+}
+
+impl GameEvent {
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Death{..} => "death",
+			Self::WaveStart{..} => "wave",
+			Self::GameOver{..} => "gameover",
+			Self::Reset{..} => "reset"
+		}
+	}
+
+	pub fn fields(&self) -> Value {
+		match self {
+			Self::Death{player, cause} => json!({"player": player.0, "cause": cause}),
+			Self::WaveStart{wave} => json!({"wave": wave}),
+			Self::GameOver{won, scores} => json!({"won": won, "scores": scores}),
+			Self::Reset{map, gamemode} => json!({"map": map, "gamemode": gamemode.name()})
+		}
+	}
+}
+
+/** Writes events as JSON lines. Clones write to the same place, so every part of the server can have one */
+#[derive(Clone)]
+pub struct EventLog {
+	out: Arc<Mutex<Box<dyn Write + Send>>>
+}
+
+impl EventLog {
+
+	pub fn stdout() -> Self {
+		Self::to_writer(Box::new(io::stdout()))
+	}
+
+	/** Append to a file, creating it if it doesn't exist */
+	pub fn open(path: &Path) -> Result<Self> {
+		let file = OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(path)
+			.map_err(|err| aerr!("can't open event log {}: {}", path.display(), err))?;
+		Ok(Self::to_writer(Box::new(file)))
+	}
+
+	pub fn to_writer(out: Box<dyn Write + Send>) -> Self {
+		Self {out: Arc::new(Mutex::new(out))}
+	}
+
+	/** Write an event with the fields of a JSON object. A log that can't be written to is not worth stopping the server for */
+	pub fn log(&self, level: Level, event: &str, fields: Value) {
+		let line = entry(Utc::now(), level, event, fields).to_string();
+		if let Ok(mut out) = self.out.lock() {
+			let _ = writeln!(out, "{}", line);
+			let _ = out.flush();
+		}
+	}
+
+	pub fn info(&self, event: &str, fields: Value) {
+		self.log(Level::Info, event, fields);
+	}
+
+	pub fn warning(&self, event: &str, fields: Value) {
+		self.log(Level::Warning, event, fields);
+	}
+
+	pub fn error(&self, event: &str, fields: Value) {
+		self.log(Level::Error, event, fields);
+	}
+
+	pub fn game_event(&self, event: &GameEvent) {
+		self.info(event.name(), event.fields());
+	}
+}
+
+fn entry(time: DateTime<Utc>, level: Level, event: &str, fields: Value) -> Value {
+	let mut entry = json!({
+		"time": time.to_rfc3339_opts(SecondsFormat::Millis, true),
+		"level": level,
+		"event": event
+	});
+	if let (Some(entry), Value::Object(fields)) = (entry.as_object_mut(), fields) {
+		entry.extend(fields);
+	}
+	entry
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use chrono::TimeZone;
+
+	#[test]
+	fn test_entries_are_flat_json() {
+		let time = Utc.with_ymd_and_hms(2021, 3, 4, 5, 6, 7).unwrap();
+		let death = GameEvent::Death{player: PlayerId("alice".to_string()), cause: "shot by bob".to_string()};
+		assert_eq!(
+			entry(time, Level::Info, death.name(), death.fields()).to_string(),
+			r#"{"cause":"shot by bob","event":"death","level":"info","player":"alice","time":"2021-03-04T05:06:07.000Z"}"#
+		);
+		assert_eq!(entry(time, Level::Error, "error", json!(null))["level"], "error");
+	}
+}
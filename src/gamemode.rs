@@ -9,14 +9,72 @@ use crate::{
 pub enum GameMode {
 	PillarDefence,
 	Survival,
-	PvP
+	PvP,
+	Teams,
+	CaptureTheFlag,
+	KingOfTheHill,
+	ControlPoints,
+	Escort,
+	/** Nothing is played; admins and builders change the map */
+	Editor
 }
 
 impl GameMode {
 	
+	/** All gamemodes that can be played */
+	pub const ALL: [GameMode; 8] = [
+		Self::Survival,
+		Self::PillarDefence,
+		Self::ControlPoints,
+		Self::Escort,
+		Self::PvP,
+		Self::Teams,
+		Self::CaptureTheFlag,
+		Self::KingOfTheHill
+	];
+	
+	/** The name to choose this gamemode with */
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Survival => "survival",
+			Self::PillarDefence => "pillars",
+			Self::ControlPoints => "control",
+			Self::Escort => "escort",
+			Self::PvP => "pvp",
+			Self::Teams => "teams",
+			Self::CaptureTheFlag => "ctf",
+			Self::KingOfTheHill => "koth",
+			Self::Editor => "editor"
+		}
+	}
+	
 	pub fn has_pillars(self) -> bool {
 		self == Self::PillarDefence
 	}
+	
+	/** Whether players fight each other instead of monsters */
+	pub fn is_pvp(self) -> bool {
+		matches!(self, Self::PvP | Self::Teams | Self::CaptureTheFlag | Self::KingOfTheHill)
+	}
+	
+	pub fn has_teams(self) -> bool {
+		matches!(self, Self::Teams | Self::CaptureTheFlag | Self::KingOfTheHill)
+	}
+	
+	/** Whether the map's control zones are played for */
+	pub fn has_zones(self) -> bool {
+		matches!(self, Self::KingOfTheHill | Self::ControlPoints)
+	}
+	
+	/** The score that ends a round unless configured otherwise. Zones score a point for every step they are held */
+	pub fn default_score_limit(self) -> Option<u64> {
+		match self {
+			Self::PvP | Self::Teams => Some(20),
+			Self::CaptureTheFlag => Some(3),
+			Self::KingOfTheHill => Some(1000),
+			Self::Survival | Self::PillarDefence | Self::ControlPoints | Self::Escort | Self::Editor => None
+		}
+	}
 }
 
 impl FromStr for GameMode {
@@ -27,6 +85,14 @@ impl FromStr for GameMode {
 			"survival" => Ok(Self::Survival),
 			"pillars" => Ok(Self::PillarDefence),
 			"pvp" => Ok(Self::PvP),
+			"teams" => Ok(Self::Teams),
+			"tdm" => Ok(Self::Teams),
+			"ctf" => Ok(Self::CaptureTheFlag),
+			"koth" => Ok(Self::KingOfTheHill),
+			"control" => Ok(Self::ControlPoints),
+			"escort" => Ok(Self::Escort),
+			"payload" => Ok(Self::Escort),
+			"editor" => Ok(Self::Editor),
 			_ => Err(aerr!("'{}' is not a valid gamemode", s))
 		}
 	}
@@ -2,16 +2,21 @@
 
 use std::collections::HashMap;
 use std::io;
+use std::time::{Duration, Instant};
 
 use serde_json::{Value, json};
 use serde::{Serialize, Deserialize};
 use unicode_categories::UnicodeCategories;
-use chrono::Utc;
+use mio::{Poll, Events, Token, Ready, PollOpt, Registration, SetReadiness};
 
 use crate::{
 	controls::{Control, Action},
-	server::Server,
+	server::{Server, listener_token, parse_token},
 	sprite::Sprite,
+	eventlog::EventLog,
+	access::{AccessList, Peer},
+	accounts::{Accounts, LoginAttempts, PasswordChecker},
+	chat::{ChatHistory, MAX_CHAT_LENGTH, sanitize},
 	PlayerId
 };
 
@@ -20,8 +25,14 @@ use crate::{
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all="lowercase")]
 enum Message {
-	Introduction(String, String),
+	Introduction(String, String, #[serde(default)] Option<String>),
 	Chat(String),
+	/** An emote, shown as `* name text` */
+	Me(String),
+	/** A private message to another player */
+	Msg(String, String),
+	TeamChat(String),
+	Password(String),
 	Input(Value)
 }
 
@@ -38,67 +49,214 @@ macro_rules! merr {
 }
 
 
+const WAKER_TOKEN: Token = Token(usize::MAX - 1);
+/** Logins beyond this many that wait for their password check are refused */
+const MAX_PENDING_LOGINS: usize = 32;
+
+/** A connection that waits for its password to be checked before it joins */
+struct PendingLogin {
+	id: (usize, usize),
+	player: PlayerId,
+	sprite: Sprite,
+	peer: Peer
+}
+
 pub struct GameServer {
 	players: HashMap<(usize, usize), PlayerId>,
 	connections: HashMap<PlayerId, (usize, usize)>,
 	servers: Vec<Box<dyn Server>>,
-	#[allow(dead_code)]
-	admins: String
+	admins: String,
+	builders: String,
+	access: AccessList,
+	accounts: Accounts,
+	login_attempts: LoginAttempts,
+	password_checker: PasswordChecker,
+	pending_logins: HashMap<u64, PendingLogin>,
+	next_ticket: u64,
+	log: EventLog,
+	chat_history: ChatHistory,
+	poll: Poll,
+	events: Events,
+	actions: Vec<Action>,
+	broken: Vec<(usize, usize)>,
+	bytes_sent: usize,
+	_waker_registration: Registration,
+	waker: SetReadiness
 }
 
 impl GameServer {
-	pub fn new(servers: Vec<Box<dyn Server>>, admins: String) -> GameServer {
-		GameServer {
+	pub fn new(servers: Vec<Box<dyn Server>>, admins: String, builders: String, access: AccessList, accounts: Accounts, log: EventLog) -> Result<GameServer, io::Error> {
+		let poll = Poll::new()?;
+		for (serverid, server) in servers.iter().enumerate() {
+			server.register(&poll, listener_token(serverid))?;
+		}
+		let (registration, waker) = Registration::new2();
+		poll.register(&registration, WAKER_TOKEN, Ready::readable(), PollOpt::edge())?;
+		Ok(GameServer {
 			players: HashMap::new(),
 			connections: HashMap::new(),
 			servers,
-			admins
-		}
+			admins,
+			builders,
+			access,
+			accounts,
+			login_attempts: LoginAttempts::default(),
+			password_checker: PasswordChecker::start(waker.clone()),
+			pending_logins: HashMap::new(),
+			next_ticket: 0,
+			log,
+			chat_history: ChatHistory::default(),
+			poll,
+			events: Events::with_capacity(1024),
+			actions: Vec::new(),
+			broken: Vec::new(),
+			bytes_sent: 0,
+			_waker_registration: registration,
+			waker
+		})
 	}
 	
-	pub fn update(&mut self) -> Vec<Action>{
-		for server in self.servers.iter_mut(){
-			let _ = server.accept_pending_connections();
-		}
-		
-		let mut actions: Vec<Action> = Vec::new();
-		let mut input = Vec::new();
-		for (serverid, server) in self.servers.iter_mut().enumerate(){
-			let (messages, left) = server.recv_pending_messages();
-			input.push((serverid, messages, left));
-		}
-		for (serverid, messages, left) in input {
-			for (id, message) in messages {
-				match serde_json::from_str(&message) {
-					Ok(msg) => {
-						match self.handle_message((serverid, id), msg){
-							Ok(Some(action)) => {actions.push(action);}
-							Ok(None) => {}
-							Err(err) => {let _ = self.send_error((serverid, id), &err.typ, &err.text);}
-						}
+	/** A handle that can interrupt `wait` from another thread */
+	pub fn waker(&self) -> SetReadiness {
+		self.waker.clone()
+	}
+	
+	/** Block until there is network activity, the waker is triggered or the timeout expires. Network activity is handled as it comes in. Returns whether anything happened before the timeout */
+	pub fn wait(&mut self, timeout: Duration) -> bool {
+		if self.poll.poll(&mut self.events, Some(timeout)).is_err() {
+			return true;
+		}
+		let ready: Vec<(Token, Ready)> = self.events.iter().map(|event| (event.token(), event.readiness())).collect();
+		for (token, readiness) in ready.iter() {
+			if *token == WAKER_TOKEN {
+				continue;
+			}
+			match parse_token(*token) {
+				(serverid, None) => {
+					if let Some(server) = self.servers.get_mut(serverid) {
+						server.accept_pending_connections(&self.poll, serverid, &self.access);
+					}
+				}
+				(serverid, Some(id)) => {
+					if serverid >= self.servers.len() {
+						continue;
 					}
-					Err(_err) => {
-						{let _ = self.send_error((serverid, id), "invalidmessage", &format!("Invalid message structure: {}", &message));}
+					if readiness.is_writable() && self.servers[serverid].flush(id).is_err() {
+						self.disconnect((serverid, id));
+						continue;
+					}
+					if readiness.is_readable() {
+						match self.servers[serverid].recv_messages(id) {
+							Ok((messages, closed)) => {
+								for message in messages {
+									self.handle_raw_message((serverid, id), &message);
+								}
+								if closed {
+									self.disconnect((serverid, id));
+								}
+							}
+							Err(_) => {
+								self.disconnect((serverid, id));
+							}
+						}
 					}
 				}
 			}
-			for id in left {
-				if let Some(player) = self.players.remove(&(serverid, id)){
-					self.connections.remove(&player);
-					self.broadcast_message(&format!("{} disconnected", player));
-					actions.push(Action::Leave(player.clone()));
-				}
+		}
+		!ready.is_empty()
+	}
+	
+	/** All actions that came in since the last update */
+	pub fn update(&mut self) -> Vec<Action>{
+		self.wait(Duration::from_millis(0));
+		// connections that were left waiting by an error get no new event, so try them again every step
+		for (serverid, server) in self.servers.iter_mut().enumerate() {
+			server.accept_pending_connections(&self.poll, serverid, &self.access);
+		}
+		for (ticket, correct) in self.password_checker.results() {
+			// the logins of connections that are gone were forgotten
+			let Some(login) = self.pending_logins.remove(&ticket) else {
+				continue;
+			};
+			let result = if correct {
+				self.login_attempts.succeed(&login.player.0, login.peer);
+				self.join(login.id, login.player, login.sprite)
+			} else {
+				let failures = self.login_attempts.fail(&login.player.0, login.peer, Instant::now());
+				self.log.warning("login", json!({"player": login.player.0, "peer": login.peer.to_string(), "failures": failures}));
+				Err(merr!("wrongpassword", "Wrong password"))
+			};
+			self.handle_result(login.id, result);
+		}
+		for id in std::mem::take(&mut self.broken) {
+			self.disconnect(id);
+		}
+		std::mem::take(&mut self.actions)
+	}
+	
+	fn handle_raw_message(&mut self, id: (usize, usize), message: &str) {
+		match serde_json::from_str(message) {
+			Ok(msg) => {
+				let result = self.handle_message(id, msg);
+				self.handle_result(id, result);
 			}
+			Err(_err) => {
+				{let _ = self.send_error(id, "invalidmessage", &format!("Invalid message structure: {}", message));}
+			}
+		}
+	}
+	
+	fn handle_result(&mut self, id: (usize, usize), result: Result<Option<Action>, MessageError>) {
+		match result {
+			Ok(Some(action)) => {self.actions.push(action);}
+			Ok(None) => {}
+			Err(err) => {let _ = self.send_error(id, &err.typ, &err.text);}
+		}
+	}
+	
+	fn disconnect(&mut self, (serverid, id): (usize, usize)) {
+		self.servers[serverid].remove(id);
+		// the id can be given to a new connection now, which must not be dropped for what happened to this one
+		self.broken.retain(|broken| *broken != (serverid, id));
+		self.pending_logins.retain(|_, login| login.id != (serverid, id));
+		if let Some(player) = self.players.remove(&(serverid, id)){
+			self.connections.remove(&player);
+			self.log.info("disconnect", json!({"player": player.0}));
+			self.broadcast_message(&format!("{} disconnected", player));
+			self.actions.push(Action::Leave(player.clone()));
 		}
-		actions
 	}
 	
-	fn send_error(&mut self, (serverid, connectionid): (usize, usize), errname: &str, err_text: &str) -> Result<(), io::Error>{
-		self.servers[serverid].send(connectionid, &json!(["error", errname, err_text]).to_string().as_str())
+	/** Send to a connection. Connections that fail get dropped at the next update */
+	fn send_raw(&mut self, (serverid, id): (usize, usize), txt: &str) -> Result<(), io::Error> {
+		let result = self.servers[serverid].send(id, txt);
+		if result.is_err() {
+			self.broken.push((serverid, id));
+		} else {
+			self.bytes_sent += txt.len();
+		}
+		result
+	}
+	
+	/** How many bytes were sent since the last time this was called */
+	pub fn take_bytes_sent(&mut self) -> usize {
+		std::mem::take(&mut self.bytes_sent)
+	}
+	
+	/** The number of connected players for every server, in the order the servers were given */
+	pub fn players_per_server(&self) -> Vec<usize> {
+		let mut counts = vec![0; self.servers.len()];
+		for (serverid, _) in self.players.keys() {
+			counts[*serverid] += 1;
+		}
+		counts
+	}
+	
+	fn send_error(&mut self, id: (usize, usize), errname: &str, err_text: &str) -> Result<(), io::Error>{
+		self.send_raw(id, json!(["error", errname, err_text]).to_string().as_str())
 	}
 	
 	pub fn broadcast_message(&mut self, text: &str){
-		println!("m {}      {}", text, Utc::now());
 		self.broadcast_json(json!(["message", text, ""]));
 	}
 	
@@ -107,17 +265,106 @@ impl GameServer {
 	}
 	
 	pub fn broadcast(&mut self, txt: &str){
-		for (serverid, id) in self.players.keys() {
-			let _ = self.servers[*serverid].send(*id, txt);
+		let ids: Vec<(usize, usize)> = self.players.keys().cloned().collect();
+		for id in ids {
+			let _ = self.send_raw(id, txt);
 		}
 	}
 	
 	pub fn send(&mut self, player: &PlayerId, value: Value) -> Result<(), io::Error> {
 		match self.connections.get(player) {
-			Some((serverid, id)) => {
-				self.servers[*serverid].send(*id, value.to_string().as_str())
+			Some(id) => {
+				self.send_raw(*id, value.to_string().as_str())
 			}
-			None => Err(io::Error::new(io::ErrorKind::Other, "unknown player name"))
+			None => Err(io::Error::other("unknown player name"))
+		}
+	}
+	
+	/** Send a public chat line to everyone and keep it for players who join later */
+	fn broadcast_chat(&mut self, line: String) {
+		self.broadcast_json(json!(["message", line, "chat"]));
+		self.chat_history.push(line);
+	}
+	
+	/** Send a chat message to the players in a team */
+	pub fn send_team_chat(&mut self, player: &PlayerId, team: &[PlayerId], text: &str) {
+		self.log.info("chat", json!({"player": player.0, "team": team.iter().map(|member| &member.0).collect::<Vec<_>>(), "text": text}));
+		let line = format!("[team] {}: {}", player, text);
+		for member in team {
+			let _ = self.send(member, json!(["message", line, "team"]));
+		}
+	}
+	
+	/** Whether the player is one of the server admins */
+	pub fn is_admin(&self, player: &PlayerId) -> bool {
+		name_in_list(&self.admins, player)
+	}
+	
+	/** Whether the player may change maps in the editor */
+	pub fn is_builder(&self, player: &PlayerId) -> bool {
+		self.is_admin(player) || name_in_list(&self.builders, player)
+	}
+	
+	pub fn set_admins(&mut self, admins: String) {
+		self.admins = admins;
+	}
+	
+	pub fn set_builders(&mut self, builders: String) {
+		self.builders = builders;
+	}
+	
+	/** The names of the connected players */
+	pub fn players(&self) -> Vec<PlayerId> {
+		let mut players: Vec<PlayerId> = self.connections.keys().cloned().collect();
+		players.sort_by(|a, b| a.0.cmp(&b.0));
+		players
+	}
+	
+	/** Disconnect a player, telling them why */
+	pub fn kick(&mut self, player: &PlayerId, reason: &str) -> Result<(), io::Error> {
+		let id = *self.connections.get(player).ok_or_else(|| io::Error::other("unknown player name"))?;
+		let _ = self.send_error(id, "kicked", reason);
+		self.log.info("kick", json!({"player": player.0, "reason": reason}));
+		self.disconnect(id);
+		Ok(())
+	}
+	
+	/** Where a player connected from */
+	pub fn peer(&self, player: &PlayerId) -> Option<Peer> {
+		let (serverid, id) = self.connections.get(player)?;
+		Some(self.servers[*serverid].peer(*id))
+	}
+	
+	pub fn access(&self) -> &AccessList {
+		&self.access
+	}
+	
+	/** Change who may play. Connected players that aren't allowed anymore are kicked */
+	pub fn update_access<T>(&mut self, change: impl FnOnce(&mut AccessList) -> T) -> T {
+		let result = change(&mut self.access);
+		for player in self.players() {
+			let peer = self.peer(&player).unwrap_or(Peer::Unknown);
+			if let Some(refusal) = self.access.refusal(&player.0, &peer) {
+				let _ = self.kick(&player, refusal);
+			}
+		}
+		result
+	}
+	
+	pub fn accounts_mut(&mut self) -> &mut Accounts {
+		&mut self.accounts
+	}
+	
+	/** Send a message from the server operator to everyone */
+	pub fn broadcast_announcement(&mut self, text: &str) {
+		self.broadcast_json(json!(["message", text, "server"]));
+	}
+	
+	/** Tell the admins that are online about something that went wrong with the server */
+	pub fn send_admins(&mut self, text: &str) {
+		let admins: Vec<PlayerId> = self.connections.keys().filter(|player| self.is_admin(player)).cloned().collect();
+		for admin in admins {
+			let _ = self.send(&admin, json!(["message", text, "server"]));
 		}
 	}
 	
@@ -125,15 +372,35 @@ impl GameServer {
 		self.send(player, json!(["error", errname, err_text]))
 	}
 	
+	/** Let a connection play as this player, now that it may */
+	fn join(&mut self, id: (usize, usize), player: PlayerId, sprite: Sprite) -> Result<Option<Action>, MessageError> {
+		// another connection can have taken the name while the password was checked
+		if self.connections.contains_key(&player) {
+			return Err(merr!("nametaken", "Another connection to this player exists already"));
+		}
+		self.log.info("connect", json!({"player": player.0}));
+		self.broadcast_message(&format!("{} connected", player));
+		self.players.insert(id, player.clone());
+		self.connections.insert(player.clone(), id);
+		if self.send(&player, json!(["connected", format!("successfully connected as {}", player)])).is_err() {
+			return Err(merr!("server", "unable to send connected message"))
+		}
+		let history: Vec<String> = self.chat_history.lines().cloned().collect();
+		for line in history {
+			let _ = self.send(&player, json!(["message", line, "chat"]));
+		}
+		Ok(Some(Action::Join(player, sprite)))
+	}
+	
 	fn handle_message(&mut self, (serverid, connectionid): (usize, usize), msg: Message) -> Result<Option<Action>, MessageError> {
 		let id = (serverid, connectionid);
 		match msg {
-			Message::Introduction(name, spritename) => {
+			Message::Introduction(name, spritename, password) => {
 				let sprite = Sprite::player_sprite(&spritename).ok_or(merr!(name, format!("Invalid player sprite: {}", spritename)))?;
 				if name.len() > 99 {
 					return Err(merr!(name, "A name can not be longer than 99 bytes"));
 				}
-				if name.len() == 0 {
+				if name.is_empty() {
 					return Err(merr!(name, "A name must have at least one character"));
 				}
 				for chr in name.chars() {
@@ -145,30 +412,105 @@ impl GameServer {
 					return Err(merr!(action, "You can not change your name"));
 				}
 				let player = PlayerId(name);
+				if let Some(refusal) = self.access.refusal(&player.0, &self.servers[serverid].peer(connectionid)) {
+					return Err(merr!("banned", refusal));
+				}
 				if self.connections.contains_key(&player) {
 					return Err(merr!("nametaken", "Another connection to this player exists already"));
 				}
-				self.broadcast_message(&format!("{} connected", player));
-				self.players.insert(id, player.clone());
-				self.connections.insert(player.clone(), id);
-				if let Err(_) = self.send(&player, json!(["connected", format!("successfully connected as {}", player)])){
-					return Err(merr!("server", "unable to send connected message"))
+				if self.pending_logins.values().any(|login| login.id == id) {
+					return Err(merr!(action, "Your password is being checked already"));
+				}
+				// a local user is who the system says they are, but only admins may skip the password of their name
+				let is_local_user = self.servers[serverid].get_name(connectionid).as_deref() == Some(player.0.as_str());
+				if let Some(hash) = self.accounts.password_hash(&player.0).filter(|_| !(is_local_user && self.is_admin(&player))) {
+					let Some(password) = password else {
+						return Err(merr!("passwordneeded", "This name is protected by a password"));
+					};
+					let peer = self.servers[serverid].peer(connectionid);
+					if let Some(wait) = self.login_attempts.backoff(&player.0, peer, Instant::now()) {
+						return Err(merr!("toomanyattempts", format!("Too many wrong passwords. Try again in {}s", wait.as_secs_f64().ceil())));
+					}
+					if self.pending_logins.len() >= MAX_PENDING_LOGINS {
+						return Err(merr!("busy", "Too many players are logging in. Try again in a moment"));
+					}
+					let ticket = self.next_ticket;
+					self.next_ticket += 1;
+					self.password_checker.check(ticket, hash, password);
+					self.pending_logins.insert(ticket, PendingLogin {id, player, sprite, peer});
+					return Ok(None);
+				} else if self.is_builder(&player) && !is_local_user {
+					return Err(merr!("reservedname", "This name is reserved. Connect as this user on a unix socket and set a password with /password first"));
 				}
-				Ok(Some(Action::Join(player, sprite)))
+				self.join(id, player, sprite)
 			}
 			Message::Chat(text) => {
 				let player = self.players.get(&id).ok_or(merr!(action, "Set a valid name before you send any other messages"))?.clone();
-				self.broadcast_message(&format!("{}: {}", player, text));
+				let text = chat_text(&text)?;
+				self.log.info("chat", json!({"player": player.0, "text": text}));
+				self.broadcast_chat(format!("{}: {}", player, text));
+				Ok(None)
+			}
+			Message::Me(text) => {
+				let player = self.players.get(&id).ok_or(merr!(action, "Set a valid name before you send any other messages"))?.clone();
+				let text = chat_text(&text)?;
+				self.log.info("chat", json!({"player": player.0, "text": text, "emote": true}));
+				self.broadcast_chat(format!("* {} {}", player, text));
+				Ok(None)
+			}
+			Message::Msg(to, text) => {
+				let player = self.players.get(&id).ok_or(merr!(action, "Set a valid name before you send any other messages"))?.clone();
+				let text = chat_text(&text)?;
+				let to = PlayerId(to);
+				if !self.connections.contains_key(&to) {
+					return Err(merr!("unknownplayer", format!("{} is not connected", to)));
+				}
+				self.log.info("chat", json!({"player": player.0, "to": to.0, "text": text}));
+				let _ = self.send(&to, json!(["message", format!("{} -> you: {}", player, text), "private"]));
+				let _ = self.send(&player, json!(["message", format!("you -> {}: {}", to, text), "private"]));
+				Ok(None)
+			}
+			Message::TeamChat(text) => {
+				let player = self.players.get(&id).ok_or(merr!(action, "Set a valid name before you send any other messages"))?.clone();
+				let text = chat_text(&text)?;
+				Ok(Some(Action::TeamChat(player, text)))
+			}
+			Message::Password(password) => {
+				// only the owner of a name can be connected with it
+				let player = self.players.get(&id).ok_or(merr!(action, "Set a name before you send any other messages"))?.clone();
+				self.accounts.register(&player.0, &password).map_err(|err| merr!("invalidpassword", err.to_string()))?;
+				self.log.info("password", json!({"player": player.0}));
+				let _ = self.send(&player, json!(["message", "Your password is set", "server"]));
 				Ok(None)
 			}
 			Message::Input(inp) => {
 				let player = self.players.get(&id).ok_or(merr!(action, "Set a name before you send any other messages"))?;
 				let control = Control::deserialize(&inp).map_err(|err| merr!(action, &format!("unknown action {} {}", inp, err)))?;
+				if control.is_admin_only() && !self.is_admin(player) {
+					return Err(merr!(action, "Only admins can do that"));
+				}
+				if control.is_edit() && !self.is_builder(player) {
+					return Err(merr!(action, "Only admins and builders can change the map"));
+				}
 				Ok(Some(Action::Input(player.clone(), control)))
 			}
 		}
 	}
 }
 
+/** The text of a chat message as other players may see it */
+fn chat_text(text: &str) -> Result<String, MessageError> {
+	let text = sanitize(text);
+	if text.is_empty() {
+		return Err(merr!(msg, "A chat message can not be empty"));
+	}
+	if text.chars().count() > MAX_CHAT_LENGTH {
+		return Err(merr!(msg, format!("A chat message can not be longer than {} characters", MAX_CHAT_LENGTH)));
+	}
+	Ok(text)
+}
 
-
+/** Names in a list are separated by commas or spaces */
+fn name_in_list(list: &str, player: &PlayerId) -> bool {
+	list.split(|c: char| c == ',' || c.is_whitespace()).any(|name| name == player.0)
+}
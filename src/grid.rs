@@ -25,6 +25,10 @@ impl<T: Clone> Grid<T> {
 		}
 	}
 	
+	pub fn size(&self) -> Pos {
+		self.size
+	}
+	
 	#[inline]
 	pub fn set_unchecked(&mut self, pos: Pos, val: T){
 		self.storage[(pos.x + self.size.x * pos.y) as usize] = val;
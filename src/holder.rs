@@ -1,11 +1,11 @@
 
-use std::collections::{HashMap, hash_map::{Iter, IterMut, Keys, Values}};
+use std::collections::{BTreeMap, btree_map::{Iter, IterMut, Keys, Values}};
 
 
-/** A hashmap that assigns unique keys to each inserted value by itself */
+/** A map that assigns unique keys to each inserted value by itself. Iteration happens in insertion order */
 pub struct Holder<T> {
 	counter: usize,
-	storage: HashMap<usize, T>
+	storage: BTreeMap<usize, T>
 }
 
 impl<T> Holder<T> {
@@ -13,7 +13,7 @@ impl<T> Holder<T> {
 	pub fn new() -> Holder<T> {
 		Self {
 			counter: 1,
-			storage: HashMap::new()
+			storage: BTreeMap::new()
 		}
 	}
 	
@@ -39,23 +39,23 @@ impl<T> Holder<T> {
 	}
 	
 	#[inline]
-	pub fn iter(&self) -> Iter<usize, T> {
+	pub fn iter(&self) -> Iter<'_, usize, T> {
 		self.storage.iter()
 	}
 	
 	#[inline]
-	pub fn iter_mut(&mut self) -> IterMut<usize, T> {
+	pub fn iter_mut(&mut self) -> IterMut<'_, usize, T> {
 		self.storage.iter_mut()
 	}
 	
 	#[allow(dead_code)]
 	#[inline]
-	pub fn keys(&self) -> Keys<usize, T> {
+	pub fn keys(&self) -> Keys<'_, usize, T> {
 		self.storage.keys()
 	}
 	
 	#[inline]
-	pub fn values(&self) -> Values<usize, T> {
+	pub fn values(&self) -> Values<'_, usize, T> {
 		self.storage.values()
 	}
 	
@@ -1,15 +1,20 @@
+use crate::{
+	sprite::Sprite,
+	team::flag_sprite
+};
 
-use crate::sprite::Sprite;
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Item {
-	Health
+	Health,
+	/** The flag of a team in capture the flag */
+	Flag(usize)
 }
 
 impl Item {
 	pub fn sprite(&self) -> Sprite {
 		Sprite::new(match self {
-			Self::Health => "health"
+			Self::Health => "health",
+			Self::Flag(team) => flag_sprite(*team)
 		})
 	}
 }
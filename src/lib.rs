@@ -0,0 +1,54 @@
+#![recursion_limit="512"]
+
+pub mod server;
+pub mod gameserver;
+mod util;
+pub mod controls;
+pub mod worldmessages;
+pub mod pos;
+pub mod player;
+pub mod world;
+pub mod sprite;
+pub mod timestamp;
+pub mod config;
+pub mod errors;
+mod holder;
+pub mod weapon;
+pub mod creature;
+pub mod tile;
+pub mod item;
+mod waves;
+pub mod gamemode;
+pub mod mapgen;
+pub mod mapcheck;
+pub mod editor;
+pub mod eventlog;
+pub mod metrics;
+pub mod console;
+pub mod access;
+pub mod accounts;
+pub mod chat;
+mod procgen;
+pub mod grid;
+pub mod team;
+pub mod zone;
+pub mod rotation;
+mod spatialindex;
+mod scheduler;
+mod reload;
+pub mod bench;
+pub mod runner;
+
+pub use self::{
+	pos::{Pos, Direction},
+	player::PlayerId,
+	errors::{Result},
+	sprite::Sprite,
+	server::address::Address,
+	controls::Control,
+	world::World,
+	worldmessages::WorldMessage,
+	gamemode::GameMode,
+	mapgen::{MapType, MapTemplate, BuiltinMap},
+	util::seed_rng,
+};
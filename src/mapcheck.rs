@@ -0,0 +1,281 @@
+
+use std::collections::{HashSet, VecDeque};
+use std::path::Path;
+use crate::{
+	Pos,
+	Direction,
+	tile::Tile,
+	creature::CreatureType,
+	gamemode::GameMode,
+	team::MAX_TEAMS,
+	mapgen::{MapTemplate, read_map, trace_path},
+	config::CheckMapConfig
+};
+
+/** Everything that would make a map unplayable, as messages a map maker can act on */
+pub fn check_map(map: &MapTemplate) -> Vec<String> {
+	let mut problems = Vec::new();
+	let mut check_place = |what: &str, pos: Pos| {
+		match map.ground.get(pos) {
+			None => problems.push(format!("{} {} is outside the map", what, show(pos))),
+			Some(tile) if tile.blocking() => problems.push(format!("{} {} is on a blocked tile", what, show(pos))),
+			Some(_) => {}
+		}
+	};
+	check_place("spawnpoint", map.spawnpoint);
+	for pos in map.teamspawns.iter() {
+		check_place("team spawnpoint", *pos);
+	}
+	for spawn in map.monsterspawn.iter() {
+		check_place("monster spawn", spawn.pos);
+	}
+	for pos in map.flags.iter() {
+		check_place("flag", *pos);
+	}
+	for pos in map.bases.iter() {
+		check_place("base", *pos);
+	}
+	for (center, _) in map.zones.iter() {
+		check_place("zone center", *center);
+	}
+	for pos in trace_path(&map.payload_path) {
+		check_place("payload road", pos);
+	}
+	for (center, radius) in map.zones.iter() {
+		if *radius < 0 {
+			problems.push(format!("zone {} has a negative radius", show(*center)));
+		}
+	}
+	for zone in map.spawnzones.iter() {
+		if zone.positions().any(|pos| map.ground.get(pos).is_none()) {
+			problems.push(format!("spawn zone {} is outside the map", show(zone.from)));
+		} else if zone.positions().all(|pos| map.ground.get(pos).is_some_and(Tile::blocking)) {
+			problems.push(format!("spawn zone {} has no open tiles", show(zone.from)));
+		}
+		if let Some(team) = zone.team.filter(|team| *team >= MAX_TEAMS) {
+			problems.push(format!("spawn zone {} is for team {}, but there are only {} teams", show(zone.from), team, MAX_TEAMS));
+		}
+	}
+	for spawn in map.monsterspawn.iter() {
+		if let (Some(first), Some(last)) = (spawn.first_wave, spawn.last_wave) {
+			if first > last {
+				problems.push(format!("monster spawn {} starts at wave {} but ends at wave {}", show(spawn.pos), first, last));
+			}
+		}
+	}
+	// after the last wave where something changes all waves are the same
+	let last_change = map.monsterspawn.iter()
+		.flat_map(|spawn| spawn.first_wave.into_iter().chain(spawn.last_wave.map(|last| last + 1)))
+		.max();
+	if let Some(last_change) = last_change {
+		for wave in 1..=last_change {
+			if !map.monsterspawn.iter().any(|spawn| spawn.is_active(wave) && spawn.weight > 0) {
+				problems.push(format!("no monster spawn is used in wave {}", wave));
+			}
+		}
+	} else if !map.monsterspawn.is_empty() && map.monsterspawn.iter().all(|spawn| spawn.weight == 0) {
+		problems.push("all monster spawns have weight 0".to_string());
+	}
+	for gamemode in map.gamemodes.iter() {
+		problems.extend(missing_for(map, *gamemode).into_iter().map(|what| format!("{} needs {}", gamemode.name(), what)));
+	}
+	let mut occupied = HashSet::new();
+	for (pos, creature) in map.creatures.iter() {
+		match map.ground.get(*pos) {
+			None => problems.push(format!("{:?} {} is outside the map", creature, show(*pos))),
+			Some(Tile::Sanctuary) => problems.push(format!("{:?} {} is in the sanctuary", creature, show(*pos))),
+			// pillars stand in the walls they hold up
+			Some(tile) if tile.blocking() && *creature != CreatureType::Pillar =>
+				problems.push(format!("{:?} {} is on a blocked tile", creature, show(*pos))),
+			Some(_) => {}
+		}
+		if *creature == CreatureType::Player {
+			problems.push(format!("{:?} {} can't be placed in a map", creature, show(*pos)));
+		}
+		if !occupied.insert(*pos) {
+			problems.push(format!("{:?} {} stands on another creature", creature, show(*pos)));
+		}
+	}
+	// monsters come through the gates like the players go out of them
+	let reached = reachable(
+		map,
+		map.teamspawns.iter()
+			.cloned()
+			.chain(Some(map.spawnpoint))
+			.chain(map.spawnzones.iter().flat_map(|zone| zone.positions()))
+			.collect()
+	);
+	for spawn in map.monsterspawn.iter() {
+		if map.ground.get(spawn.pos).is_some() && !reached.contains(&spawn.pos) {
+			problems.push(format!("monster spawn {} can't reach the players", show(spawn.pos)));
+		}
+	}
+	problems
+}
+
+/** Why the map can't be played in a gamemode */
+pub fn check_map_for(map: &MapTemplate, gamemode: GameMode) -> Vec<String> {
+	if !map.allows(gamemode) {
+		return vec![format!("{} is not one of the gamemodes of this map", gamemode.name())];
+	}
+	missing_for(map, gamemode).into_iter()
+		.map(|what| format!("{} needs {}", gamemode.name(), what))
+		.collect()
+}
+
+/** What the map misses to be played in a gamemode */
+fn missing_for(map: &MapTemplate, gamemode: GameMode) -> Vec<&'static str> {
+	let mut missing = Vec::new();
+	if !gamemode.is_pvp() && map.monsterspawn.is_empty() {
+		missing.push("monster spawns");
+	}
+	if gamemode == GameMode::CaptureTheFlag && map.flags.len() < 2 {
+		missing.push("two flags");
+	}
+	if gamemode.has_zones() && map.zones.is_empty() {
+		missing.push("zones");
+	}
+	if gamemode == GameMode::Escort && map.payload_path.is_empty() {
+		missing.push("a payload path");
+	}
+	missing
+}
+
+/** Check a map file and print what is wrong with it. Returns whether it can be played */
+pub fn run_check_map(config: CheckMapConfig) -> bool {
+	let map = match read_map(&config.file) {
+		Ok(map) => map,
+		Err(err) => {
+			println!("{}", err);
+			return false;
+		}
+	};
+	let gamemodes: Vec<GameMode> = match config.game_mode {
+		Some(gamemode) => vec![gamemode],
+		None if map.gamemodes.is_empty() => GameMode::ALL.to_vec(),
+		None => map.gamemodes.clone()
+	};
+	let missing: Vec<String> = gamemodes.iter()
+		.flat_map(|gamemode| check_map_for(&map, *gamemode))
+		.collect();
+	for text in missing.iter() {
+		println!("{}: {}", show_path(&config.file), text);
+	}
+	let playable: Vec<&str> = gamemodes.iter()
+		.filter(|gamemode| check_map_for(&map, **gamemode).is_empty())
+		.map(|gamemode| gamemode.name())
+		.collect();
+	if playable.is_empty() {
+		println!("{}: can't be played in any gamemode", show_path(&config.file));
+		return false;
+	}
+	let title = match (&map.name, &map.author) {
+		(Some(name), Some(author)) => format!("'{}' by {}, ", name, author),
+		(Some(name), None) => format!("'{}', ", name),
+		_ => String::new()
+	};
+	println!("{}: ok, {}{}x{}, playable in {}", show_path(&config.file), title, map.size.x, map.size.y, playable.join(", "));
+	true
+}
+
+fn reachable(map: &MapTemplate, start: Vec<Pos>) -> HashSet<Pos> {
+	let mut reached = HashSet::new();
+	let mut frontier: VecDeque<Pos> = start.into_iter().filter(|pos| map.ground.get(*pos).is_some()).collect();
+	while let Some(pos) = frontier.pop_front() {
+		if !reached.insert(pos) {
+			continue;
+		}
+		for dir in Direction::DIRECTIONS.iter() {
+			let next = pos + *dir;
+			if map.ground.get(next).is_some_and(|tile| !tile.blocking() || tile == &Tile::Gate) {
+				frontier.push_back(next);
+			}
+		}
+	}
+	reached
+}
+
+/** Positions are written like in the map files */
+fn show(pos: Pos) -> String {
+	format!("[{}, {}]", pos.x, pos.y)
+}
+
+fn show_path(path: &Path) -> String {
+	path.to_string_lossy().to_string()
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const MAP: &str = r#"{
+		size: [8, 3],
+		spawnpoint: [1, 1],
+		monsterspawn: [[6, 1]],
+		ground: [
+			"XXXXXXXX",
+			"X+=..X.X",
+			"XXXXXXXX"
+		],
+		creatures: [[[4, 1], "Pillar"], [[4, 1], "Zombie"], [[0, 0], "Pillar"], [[5, 1], "Troll"], [[1, 1], "Zombie"]],
+		flags: [[9, 1]]
+	}"#;
+
+	#[test]
+	fn test_problems_are_found() {
+		let map: MapTemplate = json5::from_str(MAP).unwrap();
+		assert_eq!(check_map(&map), vec![
+			"flag [9, 1] is outside the map",
+			"Zombie [4, 1] stands on another creature",
+			"Troll [5, 1] is on a blocked tile",
+			"Zombie [1, 1] is in the sanctuary",
+			"monster spawn [6, 1] can't reach the players"
+		]);
+		assert_eq!(check_map_for(&map, GameMode::CaptureTheFlag), vec!["ctf needs two flags"]);
+		assert!(check_map_for(&map, GameMode::PillarDefence).is_empty());
+	}
+
+	#[test]
+	fn test_spawn_and_gamemode_problems() {
+		let map: MapTemplate = json5::from_str(r#"{
+			name: "Lake",
+			gamemodes: ["escort", "pillars"],
+			legend: {"w": "water"},
+			size: [8, 3],
+			spawnpoint: [1, 1],
+			monsterspawn: [{pos: [5, 1], last_wave: 2}, {pos: [4, 1], weight: 3, first_wave: 4}, {pos: [3, 1], first_wave: 6, last_wave: 5}],
+			spawnzones: [{from: [1, 1], to: [2, 1], team: 7}, {from: [1, 0]}, {from: [7, 1], to: [8, 1]}],
+			ground: [
+				"XXXXXXXX",
+				"X+=...wX",
+				"XXXXXXXX"
+			],
+			creatures: []
+		}"#).unwrap();
+		assert_eq!(map.ground.get(Pos::new(6, 1)), Tile::from_name("water").as_ref());
+		assert_eq!(map.monsterspawn[1].weight, 3);
+		assert_eq!(check_map(&map), vec![
+			"spawn zone [1, 1] is for team 7, but there are only 6 teams",
+			"spawn zone [1, 0] has no open tiles",
+			"spawn zone [7, 1] is outside the map",
+			"monster spawn [3, 1] starts at wave 6 but ends at wave 5",
+			"no monster spawn is used in wave 3",
+			"escort needs a payload path"
+		]);
+		assert_eq!(check_map_for(&map, GameMode::Teams), vec!["teams is not one of the gamemodes of this map"]);
+		assert!(check_map_for(&map, GameMode::PillarDefence).is_empty());
+		assert!(json5::from_str::<MapTemplate>(&MAP.replace("creatures", "legend: {\"ab\": \"wall\"}, creatures")).is_err());
+		assert!(json5::from_str::<MapTemplate>(&MAP.replace("creatures", "gamemodes: [\"chess\"], creatures")).is_err());
+	}
+
+	#[test]
+	fn test_ground_must_match_size() {
+		let short = MAP.replace("\"X+=..X.X\",", "");
+		let err = json5::from_str::<MapTemplate>(&short).unwrap_err();
+		assert!(err.to_string().contains("ground has 2 rows but the map is 3 high"), "{}", err);
+		let narrow = MAP.replace("X+=..X.X", "X+=..X.");
+		let err = json5::from_str::<MapTemplate>(&narrow).unwrap_err();
+		assert!(err.to_string().contains("row 1 of ground is 7 wide but the map is 8 wide"), "{}", err);
+	}
+}
@@ -1,5 +1,8 @@
 
 use std::str::FromStr;
+use std::fs;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use serde::{Serialize, de, Deserialize, Deserializer};
 use rand::Rng;
 use crate::{
@@ -7,12 +10,14 @@ use crate::{
 	Direction,
 	tile::{Tile, FloorType, WallType, ObstacleType},
 	creature::CreatureType,
-	util::randomize,
+	util::{randomize, rng, random, partition_by},
 	errors::AnyError,
 	aerr,
 	grid::Grid,
 	gamemode::GameMode,
-	pos::Distance
+	pos::Distance,
+	procgen::{create_cave_map, create_dungeon_map},
+	mapcheck::check_map
 };
 
 
@@ -23,19 +28,117 @@ pub struct MapTemplate {
 	pub ground: Grid<Tile>,
 	pub creatures: Vec<(Pos, CreatureType)>,
 	pub spawnpoint: Pos,
-	pub monsterspawn: Vec<Pos>,
+	pub monsterspawn: Vec<MonsterSpawn>,
+	/** Where each team spawns in team games. Teams without an entry share the earlier ones */
+	pub teamspawns: Vec<Pos>,
+	/** Areas where players spawn instead of at the spawnpoint */
+	pub spawnzones: Vec<SpawnZone>,
+	/** Where the flag of each team is in capture the flag */
+	pub flags: Vec<Pos>,
+	/** Where each team brings captured flags. Defaults to the position of their own flag */
+	pub bases: Vec<Pos>,
+	/** The center and radius of each control zone */
+	pub zones: Vec<(Pos, i64)>,
+	/** The corners of the route of the payload in escort, from where it starts to the exit */
+	pub payload_path: Vec<Pos>,
+	/** Survival, pillar defence and control points are won after surviving this many waves */
+	pub survive_waves: Option<usize>,
+	/** The gamemodes this map is made for. Empty when it can be played in all of them */
+	pub gamemodes: Vec<GameMode>,
+	pub name: Option<String>,
+	pub author: Option<String>,
+	pub description: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+impl MapTemplate {
+	/** Whether the map is made for a gamemode. Every map can be edited */
+	pub fn allows(&self, gamemode: GameMode) -> bool {
+		self.gamemodes.is_empty() || self.gamemodes.contains(&gamemode) || gamemode == GameMode::Editor
+	}
+}
+
+/** A place where monsters come from */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MonsterSpawn {
+	pub pos: Pos,
+	/** How often monsters come from here compared to the other spawns */
+	pub weight: u32,
+	pub first_wave: Option<usize>,
+	pub last_wave: Option<usize>
+}
+
+impl MonsterSpawn {
+	pub fn new(pos: Pos) -> Self {
+		Self {pos, weight: 1, first_wave: None, last_wave: None}
+	}
+	
+	pub fn is_active(&self, wave: usize) -> bool {
+		self.first_wave.is_none_or(|first| wave >= first) && self.last_wave.is_none_or(|last| wave <= last)
+	}
+}
+
+/** A rectangle where players spawn. Zones with a team are only used by that team in team games */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpawnZone {
+	pub from: Pos,
+	/** The opposite corner. Without it the zone is only one tile */
+	#[serde(default)]
+	pub to: Option<Pos>,
+	#[serde(default)]
+	pub team: Option<usize>
+}
+
+impl SpawnZone {
+	pub fn positions(&self) -> impl Iterator<Item=Pos> {
+		let to = self.to.unwrap_or(self.from);
+		let low = Pos::new(self.from.x.min(to.x), self.from.y.min(to.y));
+		let high = Pos::new(self.from.x.max(to.x), self.from.y.max(to.y));
+		(low.y..=high.y).flat_map(move |y| (low.x..=high.x).map(move |x| Pos::new(x, y)))
+	}
+}
+
+/** Generated maps. Caves and dungeons have a width and height */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BuiltinMap{
-	Square
+	Square,
+	Cave(i64),
+	Dungeon(i64)
+}
+
+const DEFAULT_GENERATED_SIZE: i64 = 64;
+const MIN_GENERATED_SIZE: i64 = 40;
+const MAX_GENERATED_SIZE: i64 = 256;
+
+impl BuiltinMap {
+	pub const ALL: [BuiltinMap; 3] = [Self::Square, Self::Cave(DEFAULT_GENERATED_SIZE), Self::Dungeon(DEFAULT_GENERATED_SIZE)];
+	
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Square => "square",
+			Self::Cave(_) => "cave",
+			Self::Dungeon(_) => "dungeon"
+		}
+	}
 }
 
 impl FromStr for BuiltinMap {
 	type Err = AnyError;
+	/** A map name, optionally followed by a size like `cave:96` */
 	fn from_str(s: &str) -> Result<Self, Self::Err> {
-		match s {
-			"square" => Ok(Self::Square),
+		let (name, size_text) = partition_by(s, ":");
+		let size = if size_text.is_empty() {
+			DEFAULT_GENERATED_SIZE
+		} else {
+			let size: i64 = size_text.parse().map_err(|_| aerr!("'{}' is not a valid map size", size_text))?;
+			if !(MIN_GENERATED_SIZE..=MAX_GENERATED_SIZE).contains(&size) {
+				return Err(aerr!("map size must be between {} and {}", MIN_GENERATED_SIZE, MAX_GENERATED_SIZE));
+			}
+			size
+		};
+		match name.as_str() {
+			"square" if size_text.is_empty() => Ok(Self::Square),
+			"cave" => Ok(Self::Cave(size)),
+			"dungeon" => Ok(Self::Dungeon(size)),
 			_ => Err(aerr!("'{}' is not a valid map", s))
 		}
 	}
@@ -44,36 +147,103 @@ impl FromStr for BuiltinMap {
 #[derive(Debug, Clone)]
 pub enum MapType {
 	Builtin(BuiltinMap),
-	Custom(MapTemplate)
+	Custom(Box<MapTemplate>)
+}
+
+impl MapType {
+	/** A name for maps that don't come with one */
+	pub fn name(&self) -> &str {
+		match self {
+			Self::Builtin(builtin) => builtin.name(),
+			Self::Custom(template) => template.name.as_deref().unwrap_or("custom")
+		}
+	}
+	
+	/** The given gamemode if this map allows it, otherwise one that it does */
+	pub fn playable_gamemode(&self, gamemode: GameMode) -> GameMode {
+		match self {
+			Self::Custom(template) if !template.allows(gamemode) => template.gamemodes[0],
+			_ => gamemode
+		}
+	}
+}
+
+pub fn load_map(builtin: BuiltinMap, custom_map: Option<&Path>) -> Result<MapType, AnyError> {
+	if let Some(map_path) = custom_map {
+		Ok(MapType::Custom(Box::new(read_map(map_path)?)))
+	} else {
+		Ok(MapType::Builtin(builtin))
+	}
+}
+
+/** Read a custom map and check that it can be played */
+pub fn read_map(path: &Path) -> Result<MapTemplate, AnyError> {
+	let maptext = fs::read_to_string(path).map_err(|err| aerr!("can't read map {}: {}", path.to_string_lossy(), err))?;
+	let template: MapTemplate = match json5::from_str(&maptext) {
+		Ok(template) => template,
+		Err(err) => return Err(aerr!("invalid map {}: {}", path.to_string_lossy(), err))
+	};
+	let problems = check_map(&template);
+	if !problems.is_empty() {
+		return Err(aerr!("invalid map {}:\n  {}", path.to_string_lossy(), problems.join("\n  ")));
+	}
+	Ok(template)
+}
+
+/** All maps in a directory. Only .json and .json5 files are read */
+pub fn load_map_dir(dir: &Path) -> Result<Vec<(String, MapType)>, AnyError> {
+	map_files(dir)?.iter()
+		.map(|path| load_named_map(path))
+		.collect()
+}
+
+/** The map files in a directory, sorted */
+pub fn map_files(dir: &Path) -> Result<Vec<PathBuf>, AnyError> {
+	let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+		.map_err(|err| aerr!("can't read map directory {}: {}", dir.to_string_lossy(), err))?
+		.filter_map(|entry| Some(entry.ok()?.path()))
+		.filter(|path| path.extension().is_some_and(|extension| extension == "json" || extension == "json5"))
+		.collect();
+	paths.sort();
+	Ok(paths)
+}
+
+/** A custom map with the name it gives itself, or else the name of its file, for votes and announcements */
+pub fn load_named_map(path: &Path) -> Result<(String, MapType), AnyError> {
+	let template = read_map(path)?;
+	let name = template.name.clone().unwrap_or_else(||
+		path.file_stem()
+			.map(|stem| stem.to_string_lossy().to_string())
+			.unwrap_or_else(|| path.to_string_lossy().to_string())
+	);
+	Ok((name, MapType::Custom(Box::new(template))))
 }
 
 pub fn create_map(typ: &MapType, gamemode: GameMode) -> MapTemplate {
 	match typ {
 		MapType::Builtin(BuiltinMap::Square) => create_square_map(gamemode),
-		MapType::Custom(template) => template.clone()
+		MapType::Builtin(BuiltinMap::Cave(size)) => create_cave_map(gamemode, *size),
+		MapType::Builtin(BuiltinMap::Dungeon(size)) => create_dungeon_map(gamemode, *size),
+		MapType::Custom(template) => (**template).clone()
 	}
 }
 
 
 fn create_square_map(gamemode: GameMode) -> MapTemplate {
 	let size = Pos::new(64, 64);
-	let mut map = MapTemplate {
-		size,
-		ground: Grid::new(size, Tile::Floor(FloorType::Dirt)),
-		creatures: Vec::new(),
-		spawnpoint: Pos::new(size.x / 2, size.y / 2),
-		monsterspawn: vec![Pos::new(0,0), Pos::new(size.x - 1, 0), Pos::new(0, size.y - 1), Pos::new(size.x - 1, size.y - 1)],
-	};
+	let monsterspawn = vec![Pos::new(0,0), Pos::new(size.x - 1, 0), Pos::new(0, size.y - 1), Pos::new(size.x - 1, size.y - 1)];
+	let mut map = new_template(size, Tile::Floor(FloorType::Dirt), monsterspawn);
+	let center = map.spawnpoint;
+	let sanctuaries = add_mode_features(&mut map, gamemode);
 
 	for x in 0..map.size.x {
 		for y in 0..map.size.y {
 			let pos = Pos::new(x, y);
-			let dspawn = (Pos::new(x, y) - map.spawnpoint).abs();
-			let floor = if dspawn.x <= 3 && dspawn.y <= 3 {
-				Tile::Sanctuary
-			} else if dspawn.x <= 4 && dspawn.y <= 4 && dspawn.x != dspawn.y{
-				Tile::Gate
-			} else if dspawn.x <= 1 || dspawn.y <= 1 {
+			let dspawn = sanctuaries.iter()
+				.map(|sanctuary| (pos - *sanctuary).abs())
+				.min_by_key(|d| d.x.max(d.y))
+				.unwrap();
+			let floor = if dspawn.x <= 1 || dspawn.y <= 1 {
 				Tile::Floor(FloorType::Dirt)
 			} else {
 				Tile::Floor([FloorType::Grass1, FloorType::Grass2, FloorType::Grass3][randomize((x+1) as u32 + randomize((y+1) as u32)) as usize % 3])
@@ -81,35 +251,139 @@ fn create_square_map(gamemode: GameMode) -> MapTemplate {
 			map.ground.set(pos, floor);
 		}
 	}
+	for sanctuary in sanctuaries.iter() {
+		place_sanctuary(&mut map.ground, *sanctuary);
+	}
+	if gamemode.has_pillars() {
+		place_pillars(&mut map);
+	}
 	
 	let d: Vec<(i64, i64)> = vec![(1, 1), (1, -1), (-1, 1), (-1, -1)];
 	for (dx, dy) in d {
-		for (px, py) in &[(3, 3), (4, 3), (4, 2), (3, 4), (2, 4), (4, 4)] {
-			map.ground.set(map.spawnpoint + Pos::new(px * dx, py * dy), Tile::Wall(WallType::Wall));
-		}
-		if gamemode.has_pillars() {
-			map.ground.set(map.spawnpoint + Pos::new(4 * dx, 4 * dy), Tile::Wall(WallType::Rubble));
-			map.creatures.push((map.spawnpoint + Pos::new(4*dx, 4*dy), CreatureType::Pillar));
-		}
-		
-		if rand::random() {
+		if random() {
 			let lakepos = Pos::new(
-					rand::thread_rng().gen_range(12..size.x / 2 - 8) * dx,
-					rand::thread_rng().gen_range(12..size.y / 2 - 8) * dy
-				) + map.spawnpoint;
+					rng().gen_range(12..size.x / 2 - 8) * dx,
+					rng().gen_range(12..size.y / 2 - 8) * dy
+				) + center;
 			let mut p = lakepos;
 			for _i in 0..16 {
 				map.ground.set(p, Tile::Obstacle(ObstacleType::Water));
-				p = p + Direction::DIRECTIONS[rand::thread_rng().gen_range(0..4)];
+				p = p + Direction::DIRECTIONS[rng().gen_range(0..4)];
 				if lakepos.distance_to(p) > Distance(4){
 					break;
 				}
 			}
 		}
 	}
+	// keep the road of the payload clear
+	for pos in trace_path(&map.payload_path) {
+		map.ground.set(pos, Tile::Floor(FloorType::Dirt));
+	}
 	map
 }
 
+/** A map filled with one tile, with the spawnpoint in the center and nothing else */
+pub(crate) fn new_template(size: Pos, filler: Tile, monsterspawn: Vec<Pos>) -> MapTemplate {
+	MapTemplate {
+		size,
+		ground: Grid::new(size, filler),
+		creatures: Vec::new(),
+		spawnpoint: Pos::new(size.x / 2, size.y / 2),
+		monsterspawn: monsterspawn.into_iter().map(MonsterSpawn::new).collect(),
+		teamspawns: Vec::new(),
+		spawnzones: Vec::new(),
+		flags: Vec::new(),
+		bases: Vec::new(),
+		zones: Vec::new(),
+		payload_path: Vec::new(),
+		survive_waves: None,
+		gamemodes: Vec::new(),
+		name: None,
+		author: None,
+		description: None,
+	}
+}
+
+/** Add what the gamemode needs around the spawnpoint, scaled to the size of the map. Returns where the sanctuaries go */
+pub(crate) fn add_mode_features(map: &mut MapTemplate, gamemode: GameMode) -> Vec<Pos> {
+	let extent = map.size.x.min(map.size.y);
+	let scale = |n: i64| n * extent / 64;
+	let center = map.spawnpoint;
+	match gamemode {
+		GameMode::KingOfTheHill => {
+			map.zones = vec![(center, 3)];
+		}
+		GameMode::ControlPoints => {
+			map.zones = [Pos::new(-1, 0), Pos::new(1, 0), Pos::new(0, -1), Pos::new(0, 1)]
+				.iter()
+				.map(|offset| (center + *offset * scale(14), 2))
+				.collect();
+		}
+		GameMode::Escort => {
+			map.payload_path = [Pos::new(0, 7), Pos::new(0, scale(16)), Pos::new(scale(20), scale(16)), Pos::new(scale(20), scale(28))]
+				.iter()
+				.map(|offset| center + *offset)
+				.collect();
+		}
+		_ => {}
+	}
+	if gamemode.has_teams() {
+		let offsets = [Pos::new(-1, 0), Pos::new(1, 0), Pos::new(0, -1), Pos::new(0, 1)];
+		map.teamspawns = offsets.iter().map(|offset| center + *offset * scale(20)).collect();
+		if gamemode == GameMode::CaptureTheFlag {
+			// just outside the gate that faces the center
+			map.flags = offsets.iter().map(|offset| center + *offset * (scale(20) - 7)).collect();
+		}
+		map.teamspawns.clone()
+	} else {
+		vec![center]
+	}
+}
+
+/** A healing area with gates on every side */
+pub(crate) fn place_sanctuary(ground: &mut Grid<Tile>, sanctuary: Pos) {
+	for dx in -4..=4_i64 {
+		for dy in -4..=4_i64 {
+			let tile = if dx.abs() <= 3 && dy.abs() <= 3 {
+				Tile::Sanctuary
+			} else if dx.abs() != dy.abs() {
+				Tile::Gate
+			} else {
+				continue;
+			};
+			ground.set(sanctuary + Pos::new(dx, dy), tile);
+		}
+	}
+	for (dx, dy) in &[(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+		for (px, py) in &[(3, 3), (4, 3), (4, 2), (3, 4), (2, 4), (4, 4)] {
+			ground.set(sanctuary + Pos::new(px * dx, py * dy), Tile::Wall(WallType::Wall));
+		}
+	}
+}
+
+/** Pillars in the corners of the sanctuary at the spawnpoint */
+pub(crate) fn place_pillars(map: &mut MapTemplate) {
+	for (dx, dy) in &[(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+		let pos = map.spawnpoint + Pos::new(4 * dx, 4 * dy);
+		map.ground.set(pos, Tile::Wall(WallType::Rubble));
+		map.creatures.push((pos, CreatureType::Pillar));
+	}
+}
+
+/** All positions on the straight lines between the corners of a path, in order */
+pub fn trace_path(corners: &[Pos]) -> Vec<Pos> {
+	let mut path: Vec<Pos> = corners.iter().take(1).cloned().collect();
+	for corner in corners.iter().skip(1) {
+		while let Some(pos) = path.last().cloned() {
+			let Some(dir) = pos.directions_to(*corner).first().cloned() else {
+				break;
+			};
+			path.push(pos + dir);
+		}
+	}
+	path
+}
+
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 struct MapTemplateSave {
@@ -117,18 +391,148 @@ struct MapTemplateSave {
 	pub ground: Vec<String>,
 	pub creatures: Vec<(Pos, CreatureType)>,
 	pub spawnpoint: Pos,
-	pub monsterspawn: Vec<Pos>,
+	pub monsterspawn: Vec<MonsterSpawnSave>,
+	#[serde(default)]
+	pub teamspawns: Vec<Pos>,
+	#[serde(default)]
+	pub spawnzones: Vec<SpawnZone>,
+	#[serde(default)]
+	pub flags: Vec<Pos>,
+	#[serde(default)]
+	pub bases: Vec<Pos>,
+	#[serde(default)]
+	pub zones: Vec<(Pos, i64)>,
+	#[serde(default)]
+	pub payload_path: Vec<Pos>,
+	#[serde(default)]
+	pub survive_waves: Option<usize>,
+	/** Extra characters for the ground, with the names of their tiles */
+	#[serde(default)]
+	pub legend: BTreeMap<String, String>,
+	#[serde(default)]
+	pub gamemodes: Vec<String>,
+	#[serde(default)]
+	pub name: Option<String>,
+	#[serde(default)]
+	pub author: Option<String>,
+	#[serde(default)]
+	pub description: Option<String>,
+}
+
+/** Monster spawns are a position, or a position with a weight and the waves it is used in */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+enum MonsterSpawnSave {
+	Pos(Pos),
+	Spawn {
+		pos: Pos,
+		weight: Option<u32>,
+		first_wave: Option<usize>,
+		last_wave: Option<usize>
+	}
+}
+
+impl From<MonsterSpawnSave> for MonsterSpawn {
+	fn from(save: MonsterSpawnSave) -> Self {
+		match save {
+			MonsterSpawnSave::Pos(pos) => MonsterSpawn::new(pos),
+			MonsterSpawnSave::Spawn{pos, weight, first_wave, last_wave} =>
+				MonsterSpawn {pos, weight: weight.unwrap_or(1), first_wave, last_wave}
+		}
+	}
+}
+
+impl From<&MapTemplate> for MapTemplateSave {
+	fn from(map: &MapTemplate) -> Self {
+		Self {
+			size: map.size,
+			ground: (0..map.size.y)
+				.map(|y| (0..map.size.x).map(|x| map.ground.get_unchecked(Pos::new(x, y)).to_char()).collect())
+				.collect(),
+			creatures: map.creatures.clone(),
+			spawnpoint: map.spawnpoint,
+			monsterspawn: map.monsterspawn.iter()
+				.map(|spawn| match spawn {
+					MonsterSpawn{pos, weight: 1, first_wave: None, last_wave: None} => MonsterSpawnSave::Pos(*pos),
+					MonsterSpawn{pos, weight, first_wave, last_wave} => MonsterSpawnSave::Spawn {
+						pos: *pos,
+						weight: Some(*weight),
+						first_wave: *first_wave,
+						last_wave: *last_wave
+					}
+				})
+				.collect(),
+			teamspawns: map.teamspawns.clone(),
+			spawnzones: map.spawnzones.clone(),
+			flags: map.flags.clone(),
+			bases: map.bases.clone(),
+			zones: map.zones.clone(),
+			payload_path: map.payload_path.clone(),
+			survive_waves: map.survive_waves,
+			legend: BTreeMap::new(),
+			gamemodes: map.gamemodes.iter().map(|gamemode| gamemode.name().to_string()).collect(),
+			name: map.name.clone(),
+			author: map.author.clone(),
+			description: map.description.clone()
+		}
+	}
+}
+
+/** A map in the format of custom map files, with one line for each row of the ground */
+pub fn map_to_json5(map: &MapTemplate) -> String {
+	let save = serde_json::to_value(MapTemplateSave::from(map)).expect("maps can always be serialized");
+	let mut lines = Vec::new();
+	for key in ["name", "author", "description", "gamemodes", "size", "spawnpoint", "monsterspawn", "teamspawns", "spawnzones", "flags", "bases", "zones", "payload_path", "survive_waves", "creatures"].iter() {
+		let value = &save[key];
+		let required = ["size", "spawnpoint", "monsterspawn", "creatures"].contains(key);
+		if required || !(value.is_null() || value.as_array().is_some_and(Vec::is_empty)) {
+			lines.push(format!("\t{}: {},", key, value));
+		}
+	}
+	lines.push("\tground: [".to_string());
+	for row in save["ground"].as_array().expect("ground is a list of rows") {
+		lines.push(format!("\t\t{},", row));
+	}
+	lines.push("\t]".to_string());
+	format!("{{\n{}\n}}\n", lines.join("\n"))
 }
 
 impl<'de> Deserialize<'de> for MapTemplate {
 	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
 	where D: Deserializer<'de> {
-		let MapTemplateSave{size, ground, creatures, spawnpoint, monsterspawn} =
-			MapTemplateSave::deserialize(deserializer)?;
+		let MapTemplateSave{
+			size, ground, creatures, spawnpoint, monsterspawn, teamspawns, spawnzones, flags, bases, zones, payload_path, survive_waves,
+			legend, gamemodes, name, author, description
+		} = MapTemplateSave::deserialize(deserializer)?;
+		if size.x <= 0 || size.y <= 0 {
+			return Err(de::Error::custom(format!("map size {}x{} is empty", size.x, size.y)));
+		}
+		if ground.len() as i64 != size.y {
+			return Err(de::Error::custom(format!("ground has {} rows but the map is {} high", ground.len(), size.y)));
+		}
+		for (y, line) in ground.iter().enumerate() {
+			let width = line.chars().count() as i64;
+			if width != size.x {
+				return Err(de::Error::custom(format!("row {} of ground is {} wide but the map is {} wide", y, width, size.x)));
+			}
+		}
+		let mut tiles = BTreeMap::new();
+		for (key, tilename) in legend {
+			let mut chars = key.chars();
+			let (Some(c), None) = (chars.next(), chars.next()) else {
+				return Err(de::Error::custom(format!("legend key '{}' should be a single character", key)));
+			};
+			let tile = Tile::from_name(&tilename).ok_or_else(|| de::Error::custom(format!("legend gives '{}' the unknown tile '{}'", c, tilename)))?;
+			tiles.insert(c, tile);
+		}
+		let gamemodes = gamemodes.iter()
+			.map(|text| text.parse().map_err(|_| de::Error::custom(format!("'{}' in gamemodes is not a gamemode", text))))
+			.collect::<Result<Vec<GameMode>, D::Error>>()?;
 		let mut groundmap = Grid::new(size, Tile::Floor(FloorType::Dirt));
 		for (y, line) in ground.iter().enumerate(){
 			for (x, c) in line.chars().enumerate(){
-				let tile = Tile::from_char(c).ok_or_else(||de::Error::custom(format!("Invalid tile character '{}'", c)))?;
+				let tile = tiles.get(&c).cloned().or_else(|| Tile::from_char(c))
+					.ok_or_else(||de::Error::custom(format!("Invalid tile character '{}' at [{}, {}]", c, x, y)))?;
 				groundmap.set(Pos::new(x as i64, y as i64), tile);
 			}
 		}
@@ -136,10 +540,54 @@ impl<'de> Deserialize<'de> for MapTemplate {
 			size,
 			spawnpoint,
 			creatures,
-			monsterspawn,
+			monsterspawn: monsterspawn.into_iter().map(MonsterSpawn::from).collect(),
+			teamspawns,
+			spawnzones,
+			flags,
+			bases,
+			zones,
+			payload_path,
+			survive_waves,
+			gamemodes,
+			name,
+			author,
+			description,
 			ground: groundmap
 		})
 	}
 }
 
 
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_saved_maps_load_the_same() {
+		let text = r#"{
+			name: "Pond",
+			gamemodes: ["pillars"],
+			legend: {"o": "water"},
+			size: [5, 3],
+			spawnpoint: [1, 1],
+			monsterspawn: [[4, 0], {pos: [4, 2], weight: 3, last_wave: 5}],
+			spawnzones: [{from: [0, 0], to: [1, 1]}],
+			ground: [
+				"..o'R",
+				"\"+=,`",
+				"XX#..",
+			],
+			creatures: [[[3, 1], "Pillar"]],
+		}"#;
+		let map: MapTemplate = json5::from_str(text).unwrap();
+		let saved = map_to_json5(&map);
+		assert!(saved.contains("\t\t\"..~'R\",\n"), "{}", saved);
+		let loaded: MapTemplate = json5::from_str(&saved).unwrap();
+		assert_eq!(map_to_json5(&loaded), saved);
+		assert_eq!(loaded.monsterspawn, map.monsterspawn);
+		assert_eq!(loaded.spawnzones, map.spawnzones);
+		assert_eq!(loaded.name.as_deref(), Some("Pond"));
+	}
+}
@@ -0,0 +1,114 @@
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use crate::{
+	Result,
+	aerr
+};
+
+/** A slow or silent client should not keep others from being served */
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/** Numbers about the running server, for monitoring */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics {
+	pub tick_duration: Duration,
+	pub tick_overruns: usize,
+	/** Connected players by server type */
+	pub players: BTreeMap<&'static str, usize>,
+	pub creatures: usize,
+	pub bullets: usize,
+	pub bytes_sent: usize,
+	pub bytes_sent_total: u64,
+	pub wave: usize,
+	pub rounds: u64
+}
+
+impl Metrics {
+
+	/** The metrics in the Prometheus text format */
+	pub fn to_prometheus(&self) -> String {
+		let mut text = String::new();
+		let mut metric = |name: &str, kind: &str, help: &str, values: Vec<(String, String)>| {
+			let _ = writeln!(text, "# HELP battilde_{} {}", name, help);
+			let _ = writeln!(text, "# TYPE battilde_{} {}", name, kind);
+			for (labels, value) in values {
+				let _ = writeln!(text, "battilde_{}{} {}", name, labels, value);
+			}
+		};
+		let single = |value: String| vec![(String::new(), value)];
+		metric("tick_duration_seconds", "gauge", "How long the last tick took", single(self.tick_duration.as_secs_f64().to_string()));
+		metric("tick_overruns_total", "counter", "Ticks that took longer than the step duration", single(self.tick_overruns.to_string()));
+		metric(
+			"players",
+			"gauge",
+			"Connected players by server type",
+			self.players.iter().map(|(server, n)| (format!("{{server=\"{}\"}}", server), n.to_string())).collect()
+		);
+		metric("creatures", "gauge", "Creatures alive, including players", single(self.creatures.to_string()));
+		metric("bullets", "gauge", "Bullets in flight", single(self.bullets.to_string()));
+		metric("bytes_sent", "gauge", "Bytes sent to the clients in the last tick", single(self.bytes_sent.to_string()));
+		metric("bytes_sent_total", "counter", "Bytes sent to the clients since the start", single(self.bytes_sent_total.to_string()));
+		metric("wave", "gauge", "The current wave", single(self.wave.to_string()));
+		metric("rounds_total", "counter", "Rounds started since the start", single(self.rounds.to_string()));
+		text
+	}
+}
+
+/** Serve the metrics over HTTP from a thread of its own. The game updates the metrics through the returned handle */
+pub fn serve_metrics(address: SocketAddr) -> Result<Arc<Mutex<Metrics>>> {
+	let listener = TcpListener::bind(address).map_err(|err| aerr!("can't serve metrics on {}: {}", address, err))?;
+	let metrics = Arc::new(Mutex::new(Metrics::default()));
+	let shared = metrics.clone();
+	thread::spawn(move || {
+		for stream in listener.incoming().flatten() {
+			let page = match shared.lock() {
+				Ok(metrics) => metrics.to_prometheus(),
+				Err(_) => return
+			};
+			let _ = respond(stream, &page);
+		}
+	});
+	Ok(metrics)
+}
+
+/** Every request gets the metrics, whatever path it asks for */
+fn respond(mut stream: TcpStream, page: &str) -> std::io::Result<()> {
+	stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+	stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+	let mut request = [0; 1024];
+	let _ = stream.read(&mut request)?;
+	write!(
+		stream,
+		"HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+		page.len(),
+		page
+	)
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_prometheus_format() {
+		let mut metrics = Metrics {
+			tick_duration: Duration::from_millis(3),
+			wave: 4,
+			..Metrics::default()
+		};
+		metrics.players.insert("inet", 2);
+		metrics.players.insert("abstract", 1);
+		let text = metrics.to_prometheus();
+		assert!(text.contains("# TYPE battilde_tick_duration_seconds gauge\nbattilde_tick_duration_seconds 0.003\n"), "{}", text);
+		assert!(text.contains("battilde_players{server=\"abstract\"} 1\nbattilde_players{server=\"inet\"} 2\n"), "{}", text);
+		assert!(text.contains("\nbattilde_wave 4\n"), "{}", text);
+		assert!(text.lines().all(|line| line.starts_with("# ") || line.starts_with("battilde_")));
+	}
+}
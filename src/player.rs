@@ -3,11 +3,13 @@ use std::fmt;
 use serde::{Serialize, Deserialize};
 
 use crate::{
+	Pos,
 	sprite::Sprite,
-	controls::Control
+	controls::Control,
+	gamemode::GameMode
 };
 
-#[derive(Debug, Default, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Serialize, Deserialize)]
 pub struct PlayerId(pub String);
 
 impl fmt::Display for PlayerId {
@@ -21,5 +23,13 @@ pub struct Player {
 	pub plan: Option<Control>,
 	pub sprite: Sprite,
 	pub body: usize,
-	pub is_new: bool
+	pub is_new: bool,
+	pub is_bot: bool,
+	pub team: usize,
+	pub kills: u64,
+	/** Which map in the rotation this player wants next */
+	pub map_vote: Option<usize>,
+	pub mode_vote: Option<GameMode>,
+	/** Where this player is working in the map editor */
+	pub cursor: Pos
 }
@@ -0,0 +1,304 @@
+
+use std::collections::{BinaryHeap, VecDeque};
+use std::cmp::Reverse;
+use rand::Rng;
+use crate::{
+	Pos,
+	Direction,
+	tile::{Tile, FloorType, WallType},
+	creature::CreatureType,
+	util::{randomize, rng},
+	grid::Grid,
+	gamemode::GameMode,
+	mapgen::{MapTemplate, new_template, add_mode_features, place_sanctuary, place_pillars, trace_path}
+};
+
+/** How many tiles around a sanctuary are kept open */
+const SANCTUARY_CLEARING: i64 = 6;
+/** Digging through rock costs this much more than walking over an existing floor */
+const DIG_COST: usize = 4;
+
+/** Caves grown with a cellular automaton */
+pub fn create_cave_map(gamemode: GameMode, size: i64) -> MapTemplate {
+	let size = Pos::new(size, size);
+	let mut map = new_template(size, Tile::Wall(WallType::Rock), corners(size, 3));
+	let sanctuaries = add_mode_features(&mut map, gamemode);
+
+	let mut open = Grid::new(size, false);
+	for x in 1..size.x - 1 {
+		for y in 1..size.y - 1 {
+			open.set(Pos::new(x, y), rng().gen_range(0..100) >= 45);
+		}
+	}
+	for _ in 0..5 {
+		let mut next = Grid::new(size, false);
+		for x in 1..size.x - 1 {
+			for y in 1..size.y - 1 {
+				let pos = Pos::new(x, y);
+				let walls = (-1..=1)
+					.flat_map(|dx| (-1..=1).map(move |dy| pos + Pos::new(dx, dy)))
+					.filter(|neighbour| !open.get(*neighbour).cloned().unwrap_or(false))
+					.count();
+				next.set(pos, walls < 5);
+			}
+		}
+		open = next;
+	}
+	for x in 0..size.x {
+		for y in 0..size.y {
+			let pos = Pos::new(x, y);
+			if *open.get_unchecked(pos) {
+				map.ground.set(pos, cave_floor(pos));
+			}
+		}
+	}
+	finish(&mut map, gamemode, &sanctuaries, Vec::new());
+	map
+}
+
+/** Rectangular rooms joined by corridors */
+pub fn create_dungeon_map(gamemode: GameMode, size: i64) -> MapTemplate {
+	let size = Pos::new(size, size);
+	let mut map = new_template(size, Tile::Wall(WallType::Wall), corners(size, 3));
+	let sanctuaries = add_mode_features(&mut map, gamemode);
+
+	// (lowest corner, highest corner) of each room
+	let mut rooms: Vec<(Pos, Pos)> = map.monsterspawn.iter()
+		.map(|spawn| (spawn.pos - Pos::new(2, 2), spawn.pos + Pos::new(2, 2)))
+		.collect();
+	for _ in 0..(size.x * size.y / 16) {
+		let room_size = Pos::new(rng().gen_range(4..=10), rng().gen_range(4..=10));
+		let low = Pos::new(rng().gen_range(1..size.x - room_size.x - 1), rng().gen_range(1..size.y - room_size.y - 1));
+		let high = low + room_size - Pos::new(1, 1);
+		let overlaps = rooms.iter()
+			.any(|(other_low, other_high)|
+				low.x <= other_high.x + 1 && other_low.x <= high.x + 1
+				&& low.y <= other_high.y + 1 && other_low.y <= high.y + 1
+			)
+			|| sanctuaries.iter().any(|sanctuary| {
+				let d = (*sanctuary - sanctuary.clamp(low, high)).abs();
+				d.x.max(d.y) <= SANCTUARY_CLEARING + 1
+			});
+		if !overlaps {
+			rooms.push((low, high));
+		}
+	}
+	for (low, high) in rooms.iter() {
+		for x in low.x..=high.x {
+			for y in low.y..=high.y {
+				map.ground.set(Pos::new(x, y), Tile::Floor(FloorType::Stone));
+			}
+		}
+	}
+	let centers = rooms.iter().map(|(low, high)| Pos::new((low.x + high.x) / 2, (low.y + high.y) / 2)).collect();
+	finish(&mut map, gamemode, &sanctuaries, centers);
+	map
+}
+
+fn corners(size: Pos, inset: i64) -> Vec<Pos> {
+	vec![
+		Pos::new(inset, inset),
+		Pos::new(size.x - 1 - inset, inset),
+		Pos::new(inset, size.y - 1 - inset),
+		Pos::new(size.x - 1 - inset, size.y - 1 - inset)
+	]
+}
+
+fn cave_floor(pos: Pos) -> Tile {
+	Tile::Floor([FloorType::Dirt, FloorType::Dirt, FloorType::Grass1, FloorType::Stone][randomize((pos.x + 1) as u32 + randomize((pos.y + 1) as u32)) as usize % 4])
+}
+
+/** Put in the sanctuaries, pillars and everything the gamemode needs, then make sure all of it can be reached */
+fn finish(map: &mut MapTemplate, gamemode: GameMode, sanctuaries: &[Pos], extra_points: Vec<Pos>) {
+	for sanctuary in sanctuaries {
+		for dx in -SANCTUARY_CLEARING..=SANCTUARY_CLEARING {
+			for dy in -SANCTUARY_CLEARING..=SANCTUARY_CLEARING {
+				map.ground.set(*sanctuary + Pos::new(dx, dy), Tile::Floor(FloorType::Dirt));
+			}
+		}
+		place_sanctuary(&mut map.ground, *sanctuary);
+	}
+	if gamemode.has_pillars() {
+		place_pillars(map);
+	}
+	for pos in trace_path(&map.payload_path) {
+		map.ground.set(pos, Tile::Floor(FloorType::Dirt));
+	}
+	let zone_tiles: Vec<Pos> = map.zones.iter()
+		.flat_map(|(center, radius)| {
+			let radius = *radius;
+			(-radius..=radius).flat_map(move |dx| (-radius..=radius).map(move |dy| *center + Pos::new(dx, dy)))
+		})
+		.collect();
+	for pos in zone_tiles {
+		map.ground.set(pos, Tile::Floor(FloorType::Dirt));
+	}
+	for spawn in map.monsterspawn.clone() {
+		for dir in Direction::DIRECTIONS.iter() {
+			map.ground.set(spawn.pos + *dir, Tile::Floor(FloorType::Dirt));
+		}
+		map.ground.set(spawn.pos, Tile::Floor(FloorType::Dirt));
+	}
+	let mut points = key_points(map, sanctuaries);
+	points.extend(extra_points);
+	connect(&mut map.ground, &points, sanctuaries);
+}
+
+/** The places that everyone has to be able to walk to: just outside each gate, next to each pillar, and where things spawn or get carried */
+fn key_points(map: &MapTemplate, sanctuaries: &[Pos]) -> Vec<Pos> {
+	let mut points: Vec<Pos> = sanctuaries.iter()
+		.flat_map(|sanctuary| Direction::DIRECTIONS.iter().map(move |dir| *sanctuary + dir.to_position() * 5))
+		.collect();
+	for (pos, creature) in map.creatures.iter() {
+		if *creature == CreatureType::Pillar {
+			// the side that faces away from the sanctuary
+			let outside = Direction::DIRECTIONS.iter()
+				.map(|dir| *pos + *dir)
+				.max_by_key(|neighbour| distance_to_sanctuary(sanctuaries, *neighbour))
+				.unwrap();
+			points.push(outside);
+		}
+	}
+	if !sanctuaries.contains(&map.spawnpoint) {
+		points.push(map.spawnpoint);
+	}
+	points.extend(map.monsterspawn.iter().map(|spawn| spawn.pos));
+	points.extend(map.flags.iter().cloned());
+	points.extend(map.bases.iter().cloned());
+	points.extend(map.zones.iter().map(|(center, _)| *center));
+	points.extend(map.payload_path.iter().cloned());
+	points
+}
+
+fn distance_to_sanctuary(sanctuaries: &[Pos], pos: Pos) -> i64 {
+	sanctuaries.iter()
+		.map(|sanctuary| {
+			let d = (pos - *sanctuary).abs();
+			d.x.max(d.y)
+		})
+		.min()
+		.unwrap_or(i64::MAX)
+}
+
+/** Dig tunnels until every point can be reached from the first one */
+fn connect(ground: &mut Grid<Tile>, points: &[Pos], sanctuaries: &[Pos]) {
+	let Some(start) = points.first() else {
+		return;
+	};
+	let mut reached = reachable(ground, *start);
+	for point in points.iter().skip(1) {
+		if ground.get(*point).is_none() || *reached.get_unchecked(*point) {
+			continue;
+		}
+		dig(ground, *point, &reached, sanctuaries);
+		reached = reachable(ground, *start);
+	}
+}
+
+fn reachable(ground: &Grid<Tile>, start: Pos) -> Grid<bool> {
+	let mut reached = Grid::new(ground.size(), false);
+	let mut frontier = VecDeque::from(vec![start]);
+	while let Some(pos) = frontier.pop_front() {
+		if *reached.get_unchecked(pos) {
+			continue;
+		}
+		reached.set_unchecked(pos, true);
+		for dir in Direction::DIRECTIONS.iter() {
+			let next = pos + *dir;
+			if ground.get(next).is_some_and(|tile| !tile.blocking()) {
+				frontier.push_back(next);
+			}
+		}
+	}
+	reached
+}
+
+/** Carve the cheapest tunnel from a point to anywhere that is reached already, staying out of the sanctuaries */
+fn dig(ground: &mut Grid<Tile>, from: Pos, reached: &Grid<bool>, sanctuaries: &[Pos]) {
+	let mut came_from: Grid<Option<Pos>> = Grid::new(ground.size(), None);
+	let mut costs: Grid<Option<usize>> = Grid::new(ground.size(), None);
+	let mut frontier = BinaryHeap::new();
+	frontier.push(Reverse((0, from.x, from.y)));
+	costs.set(from, Some(0));
+	let mut end = None;
+	while let Some(Reverse((cost, x, y))) = frontier.pop() {
+		let pos = Pos::new(x, y);
+		if costs.get_unchecked(pos).is_some_and(|known| known < cost) {
+			continue;
+		}
+		if *reached.get_unchecked(pos) {
+			end = Some(pos);
+			break;
+		}
+		for dir in Direction::DIRECTIONS.iter() {
+			let next = pos + *dir;
+			let Some(tile) = ground.get(next) else {
+				continue;
+			};
+			if distance_to_sanctuary(sanctuaries, next) <= 4 || tile == &Tile::Wall(WallType::Rubble) {
+				continue;
+			}
+			let next_cost = cost + if tile.blocking() {DIG_COST} else {1};
+			if costs.get_unchecked(next).is_none_or(|known| next_cost < known) {
+				costs.set_unchecked(next, Some(next_cost));
+				came_from.set_unchecked(next, Some(pos));
+				frontier.push(Reverse((next_cost, next.x, next.y)));
+			}
+		}
+	}
+	let mut pos = end;
+	while let Some(current) = pos {
+		if ground.get(current).is_some_and(Tile::blocking) {
+			ground.set(current, Tile::Floor(FloorType::Dirt));
+		}
+		pos = *came_from.get_unchecked(current);
+	}
+	if ground.get(from).is_some_and(Tile::blocking) {
+		ground.set(from, Tile::Floor(FloorType::Dirt));
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{util::seed_rng, mapcheck::check_map};
+
+	/** Everything the players and monsters need is connected, when the sanctuary gates can be walked through */
+	fn assert_connected(map: &MapTemplate) {
+		let mut reached = Grid::new(map.size, false);
+		let sanctuaries = if map.teamspawns.is_empty() {vec![map.spawnpoint]} else {map.teamspawns.clone()};
+		let mut frontier = VecDeque::from(vec![sanctuaries[0]]);
+		while let Some(pos) = frontier.pop_front() {
+			if *reached.get_unchecked(pos) {
+				continue;
+			}
+			reached.set_unchecked(pos, true);
+			for dir in Direction::DIRECTIONS.iter() {
+				let next = pos + *dir;
+				if map.ground.get(next).is_some_and(|tile| !tile.blocking() || tile == &Tile::Gate) {
+					frontier.push_back(next);
+				}
+			}
+		}
+		for point in key_points(map, &sanctuaries).into_iter().chain(map.teamspawns.iter().cloned()) {
+			assert!(reached.get(point) == Some(&true), "{:?} can not be reached", point);
+		}
+		assert_eq!(check_map(map), Vec::<String>::new());
+	}
+
+	#[test]
+	fn test_generated_maps_are_connected() {
+		for seed in 0..4 {
+			for gamemode in [GameMode::PillarDefence, GameMode::Teams, GameMode::Escort].iter() {
+				for size in [40, 72].iter() {
+					seed_rng(seed);
+					let cave = create_cave_map(*gamemode, *size);
+					assert_eq!(cave.size, Pos::new(*size, *size));
+					assert_connected(&cave);
+					assert_connected(&create_dungeon_map(*gamemode, *size));
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,112 @@
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use crate::{
+	Result,
+	mapgen::{MapType, BuiltinMap, load_map_dir, load_named_map, map_files}
+};
+
+/** Where the maps of the server come from, so they can be read again when they change */
+#[derive(Debug, Clone)]
+pub struct MapSource {
+	pub builtin: BuiltinMap,
+	pub custom_maps: Vec<PathBuf>,
+	pub map_dir: Option<PathBuf>
+}
+
+impl MapSource {
+
+	/** The custom maps, or else the builtin maps with the chosen one first, followed by the maps in the map directory */
+	pub fn load(&self) -> Result<Vec<(String, MapType)>> {
+		let mut maps: Vec<(String, MapType)> = if self.custom_maps.is_empty() {
+			let first = self.builtin;
+			std::iter::once(first)
+				.chain(BuiltinMap::ALL.iter().cloned().filter(|builtin| builtin.name() != first.name()))
+				.map(|builtin| (builtin.name().to_string(), MapType::Builtin(builtin)))
+				.collect()
+		} else {
+			self.custom_maps.iter()
+				.map(|path| load_named_map(path))
+				.collect::<Result<_>>()?
+		};
+		if let Some(dir) = &self.map_dir {
+			maps.extend(load_map_dir(dir)?);
+		}
+		Ok(maps)
+	}
+
+	/** The files that the maps are read from. The map directory itself changes when files are added or removed */
+	pub fn files(&self) -> Vec<PathBuf> {
+		let mut files = self.custom_maps.clone();
+		if let Some(dir) = &self.map_dir {
+			files.push(dir.clone());
+			files.extend(map_files(dir).unwrap_or_default());
+		}
+		files
+	}
+}
+
+/** Notices changed files by their modification times */
+#[derive(Debug, Clone, Default)]
+pub struct FileWatcher {
+	files: Vec<(PathBuf, Option<SystemTime>)>
+}
+
+impl FileWatcher {
+
+	/** Watch these files from now on. Files that were watched already keep the time they were last seen with */
+	pub fn watch(&mut self, paths: Vec<PathBuf>) {
+		self.files = paths.into_iter()
+			.map(|path| {
+				let known = self.files.iter()
+					.find(|(known_path, _)| *known_path == path)
+					.map(|(_, time)| *time)
+					.unwrap_or_else(|| modified(&path));
+				(path, known)
+			})
+			.collect();
+	}
+
+	/** Whether any file changed, appeared or disappeared since the last check */
+	pub fn check(&mut self) -> bool {
+		let mut changed = false;
+		for (path, time) in self.files.iter_mut() {
+			let now = modified(path);
+			if now != *time {
+				*time = now;
+				changed = true;
+			}
+		}
+		changed
+	}
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+	fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::time::Duration;
+
+	#[test]
+	fn test_changed_files_are_noticed() {
+		let dir = std::env::temp_dir().join(format!("battilde-watch-{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let path = dir.join("map.json");
+		fs::write(&path, "{}").unwrap();
+		let mut watcher = FileWatcher::default();
+		watcher.watch(vec![path.clone()]);
+		assert!(!watcher.check());
+		let later = SystemTime::now() + Duration::from_secs(10);
+		fs::File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+		assert!(watcher.check());
+		assert!(!watcher.check());
+		fs::remove_file(&path).unwrap();
+		assert!(watcher.check());
+		fs::remove_dir(&dir).unwrap();
+	}
+}
@@ -0,0 +1,124 @@
+
+use crate::{
+	aerr,
+	Result,
+	gamemode::GameMode,
+	mapgen::MapType
+};
+
+/** What a player wants to play in the next round */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vote {
+	Map(usize),
+	GameMode(GameMode)
+}
+
+/** The maps that are played in turn */
+#[derive(Debug, Clone)]
+pub struct Rotation {
+	maps: Vec<(String, MapType)>,
+	current: usize
+}
+
+impl Rotation {
+
+	pub fn new(maps: Vec<(String, MapType)>) -> Result<Self> {
+		if maps.is_empty() {
+			return Err(aerr!("a map rotation needs at least one map"));
+		}
+		Ok(Self {maps, current: 0})
+	}
+
+	pub fn current(&self) -> &MapType {
+		&self.maps[self.current].1
+	}
+
+	pub fn current_name(&self) -> &str {
+		&self.maps[self.current].0
+	}
+
+	pub fn names(&self) -> impl Iterator<Item=&str> {
+		self.maps.iter().map(|(name, _)| name.as_str())
+	}
+
+	/** Go to the map with this name, if there is one. Returns whether there was */
+	pub fn select(&mut self, name: &str) -> bool {
+		match self.maps.iter().position(|(map_name, _)| map_name == name) {
+			Some(index) => {
+				self.current = index;
+				true
+			}
+			None => false
+		}
+	}
+	
+	/** Understand a vote for a map by its name, or else for a gamemode */
+	pub fn parse_vote(&self, text: &str) -> Result<Vote> {
+		if let Some(index) = self.maps.iter().position(|(name, _)| name == text) {
+			return Ok(Vote::Map(index));
+		}
+		match text.parse() {
+			Ok(GameMode::Editor) => Err(aerr!("the editor can't be voted for")),
+			Ok(gamemode) => Ok(Vote::GameMode(gamemode)),
+			Err(_) => Err(aerr!("'{}' is neither a map nor a gamemode. Maps: {}", text, self.names().collect::<Vec<&str>>().join(", ")))
+		}
+	}
+
+	/** Go to the map that was voted for, or else to the next map */
+	pub fn next(&mut self, vote: Option<usize>) {
+		self.current = match vote {
+			Some(index) => index % self.maps.len(),
+			None => (self.current + 1) % self.maps.len()
+		};
+	}
+}
+
+/** The option with the most votes. Ties go to whatever got its first vote earliest */
+pub fn most_voted<T: PartialEq>(votes: impl Iterator<Item=T>) -> Option<T> {
+	let mut counts: Vec<(T, usize)> = Vec::new();
+	for vote in votes {
+		match counts.iter_mut().find(|(option, _)| *option == vote) {
+			Some((_, count)) => *count += 1,
+			None => counts.push((vote, 1))
+		}
+	}
+	let mut best: Option<(T, usize)> = None;
+	for (option, count) in counts {
+		if best.as_ref().is_none_or(|(_, best_count)| count > *best_count) {
+			best = Some((option, count));
+		}
+	}
+	best.map(|(option, _)| option)
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mapgen::BuiltinMap;
+
+	#[test]
+	fn test_votes() {
+		let mut rotation = Rotation::new(vec![
+			("square".to_string(), MapType::Builtin(BuiltinMap::Square)),
+			("pvp".to_string(), MapType::Builtin(BuiltinMap::Square))
+		]).unwrap();
+		assert_eq!(rotation.parse_vote("pvp").unwrap(), Vote::Map(1), "maps go before gamemodes");
+		assert_eq!(rotation.parse_vote("ctf").unwrap(), Vote::GameMode(GameMode::CaptureTheFlag));
+		assert!(rotation.parse_vote("nowhere").is_err());
+
+		assert_eq!(most_voted(vec![2, 1, 1, 2, 3].into_iter()), Some(2));
+		assert_eq!(most_voted(vec![3, 1, 1].into_iter()), Some(1));
+		assert_eq!(most_voted(Vec::<usize>::new().into_iter()), None);
+
+		rotation.next(None);
+		assert_eq!(rotation.current_name(), "pvp");
+		rotation.next(None);
+		assert_eq!(rotation.current_name(), "square");
+		rotation.next(Some(1));
+		assert_eq!(rotation.current_name(), "pvp");
+		assert!(rotation.select("square"));
+		assert!(!rotation.select("nowhere"));
+		assert_eq!(rotation.current_name(), "square");
+	}
+}
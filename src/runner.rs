@@ -0,0 +1,448 @@
+
+use std::time::{Duration, Instant};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}};
+use mio::Ready;
+use serde_json::json;
+
+use crate::{
+	gameserver::GameServer,
+	server::Server,
+	controls::{Action, Control},
+	world::World,
+	worldmessages::MessageCache,
+	scheduler::Scheduler,
+	reload::{MapSource, FileWatcher},
+	config::Config,
+	Address,
+	eventlog::{EventLog, GameEvent},
+	metrics::{Metrics, serve_metrics},
+	console::{Console, ConsoleCommand, CONSOLE_HELP, loggable_command},
+	access::{AccessList, Peer},
+	accounts::Accounts,
+	team::RoundRules,
+	gamemode::GameMode,
+	errors::AnyError,
+	mapgen::map_to_json5,
+	util::write_file_safe,
+	aerr,
+	Result
+};
+
+const IDLE_STEP_DURATION: Duration = Duration::from_millis(500);
+/** How often the map files and the config file are checked for changes */
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+
+/** Run the game server with the given configuration until it gets interrupted */
+pub fn run_server(config: Config) {
+	
+	let log = match &config.event_log {
+		Some(path) => EventLog::open(path).unwrap_or_else(|err| exit_with(err)),
+		None => EventLog::stdout()
+	};
+	
+	let adresses = config.address.clone()
+		.unwrap_or_else(||
+			(if cfg!(target_os = "linux") {
+				vec!["abstract:battilde", "inet:127.0.0.1:9221"]
+			} else {
+				vec!["inet:127.0.0.1:9221"]
+			})
+			.iter()
+			.map(|a| a.parse().unwrap())
+			.collect()
+		);
+	let servers: Vec<Box<dyn Server>> = 
+		adresses
+		.iter()
+		.map(|a| a.to_server().unwrap())
+		.collect();
+	
+	let admins = config.admins.clone();
+	let access = match &config.access_file {
+		Some(path) => AccessList::load(path).unwrap_or_else(|err| exit_with(err)),
+		None => AccessList::default()
+	};
+	let accounts = Accounts::open(config.password_file.clone()).unwrap_or_else(|err| exit_with(err));
+	let mut gameserver = GameServer::new(servers, admins.clone(), config.builders.clone(), access, accounts, log.clone()).expect("can't set up event polling");
+	
+	let map_source = MapSource {
+		builtin: config.map,
+		custom_maps: config.custom_map.clone(),
+		map_dir: config.map_dir.clone()
+	};
+	let maps = map_source.load().unwrap_or_else(|err| exit_with(err));
+	let mut watcher = FileWatcher::default();
+	watcher.watch(map_source.files());
+	let mut config_watcher = FileWatcher::default();
+	config_watcher.watch(config.config.iter().cloned().collect());
+	let mut last_reload_check = Instant::now();
+	
+	let mut step_duration = Duration::from_millis(config.step_duration.max(1));
+	
+	let mut world = World::new(config.game_mode, maps[0].1.clone());
+	world.set_step_duration(step_duration);
+	world.set_maps(maps).expect("no maps to play");
+	world.set_round_rules(round_rules(&config));
+	world.set_min_players(config.bots);
+	// setting up resets the world a few times, which isn't worth logging
+	world.take_events();
+	let mut rounds = 1;
+	let metrics = config.metrics_address.map(|address| serve_metrics(address).unwrap_or_else(|err| exit_with(err)));
+	log.info("start", json!({
+		"addresses": adresses.iter().map(Address::to_string).collect::<Vec<String>>(),
+		"admins": admins,
+		"map": world.map_name(),
+		"gamemode": world.gamemode().name()
+	}));
+	
+	let mut message_cache = MessageCache::default();
+	
+	// close handler
+    let running = Arc::new(AtomicBool::new(true));
+    let r = running.clone();
+	let waker = gameserver.waker();
+	let handler_log = log.clone();
+	ctrlc::set_handler(move || {
+		handler_log.info("interrupt", json!({}));
+		r.store(false, Ordering::SeqCst);
+		let _ = waker.set_readiness(Ready::readable());
+	}).expect("can't set close handler");
+	
+	let console = Console::new(gameserver.waker());
+	console.read_stdin();
+	if let Some(path) = &config.control_socket {
+		console.listen(path).unwrap_or_else(|err| exit_with(err));
+	}
+	
+	
+	let mut empty_timer = 1000000;
+	let mut scheduler = Scheduler::new();
+	
+	while running.load(Ordering::SeqCst) {
+		let idle = world.nplayers() == 0 && empty_timer > 100;
+		let time_left = scheduler.time_left();
+		let mut woken = false;
+		if !time_left.is_zero() {
+			// when idle a new connection should be handled immediately
+			woken = gameserver.wait(time_left);
+			if !(idle && woken) {
+				continue;
+			}
+		}
+		scheduler.start_tick(if idle && !woken {IDLE_STEP_DURATION} else {step_duration});
+		empty_timer += 1;
+		for event in world.take_events() {
+			if let GameEvent::Reset{..} = event {
+				rounds += 1;
+			}
+			log.game_event(&event);
+		}
+		if let Some(metrics) = &metrics {
+			update_metrics(metrics, &world, &mut gameserver, &adresses, &scheduler, rounds);
+		}
+		if last_reload_check.elapsed() >= RELOAD_CHECK_INTERVAL {
+			last_reload_check = Instant::now();
+			if watcher.check() {
+				match map_source.load().and_then(|maps| world.queue_maps(maps)) {
+					Ok(()) => {
+						log.info("maps_changed", json!({}));
+						gameserver.send_admins("The maps changed on disk. They are used from the next round on, or type /reload to start a new round now");
+					}
+					Err(err) => {
+						log.error("maps_changed", json!({"error": err.to_string()}));
+						gameserver.send_admins(&err.to_string());
+					}
+				}
+				watcher.watch(map_source.files());
+			}
+			if config_watcher.check() {
+				match Config::load() {
+					Ok(changed) => {
+						let text = apply_config(changed, &mut world, &mut gameserver, &mut step_duration);
+						log.info("config_changed", json!({}));
+						gameserver.send_admins(text);
+					}
+					Err(err) => {
+						log.error("config_changed", json!({"error": err.to_string()}));
+						gameserver.send_admins(&err.to_string());
+					}
+				}
+			}
+		}
+		// before the update, so kicked players leave the world right away
+		for request in console.requests() {
+			log.info("console", json!({"command": loggable_command(&request.line)}));
+			let map_dir = config.map_dir.as_deref();
+			let access_file = config.access_file.as_deref();
+			let reply = request.line.parse()
+				.and_then(|command| run_command(command, &mut world, &mut gameserver, &mut step_duration, map_dir, access_file));
+			match reply {
+				Ok(text) => request.reply(&text),
+				Err(err) => request.reply(&err.to_string())
+			}
+		}
+		let actions = gameserver.update();
+		for action in actions {
+			match action {
+				Action::Input(player, Control::SaveMap(name)) => {
+					match save_map(&world, config.map_dir.as_deref(), &name) {
+						Ok(path) => {
+							log.info("save_map", json!({"player": player.0, "path": path}));
+							gameserver.broadcast_message(&format!("{} saved the map to {}", player, path.to_string_lossy()));
+						}
+						Err(err) => {
+							let _ = gameserver.send_player_error(&player, "invalidaction", &err.to_string());
+						}
+					}
+				}
+				Action::Input(player, Control::Reload) => {
+					match map_source.load().and_then(|maps| world.queue_maps(maps)) {
+						Ok(()) => {
+							log.info("reload", json!({"player": player.0}));
+							world.reset();
+							gameserver.broadcast_message(&format!("{} reloaded the maps", player));
+						}
+						Err(err) => {
+							let _ = gameserver.send_player_error(&player, "invalidaction", &err.to_string());
+						}
+					}
+				}
+				Action::Input(player, control) => {
+					if let Err(err) = world.control_player(player.clone(), control){
+						log.warning("control", json!({"player": player.0, "error": err.to_string()}));
+						let _ = gameserver.send_player_error(&player, "invalidaction", &err.to_string());
+					}
+				}
+				Action::TeamChat(player, text) => {
+					match world.teammates(&player) {
+						Ok(team) => gameserver.send_team_chat(&player, &team, &text),
+						Err(err) => {
+							let _ = gameserver.send_player_error(&player, "invalidaction", &err.to_string());
+						}
+					}
+				}
+				Action::Join(player, sprite) => {
+					if let Err(err) = world.add_player(&player, sprite) {
+						log.error("join", json!({"player": player.0, "error": err.to_string()}));
+						if let Err(senderr) = gameserver.send_player_error(&player, "worlderror", "invalid room or savefile") {
+							log.error("send", json!({"player": player.0, "error": senderr.to_string()}));
+						}
+					}
+				}
+				Action::Leave(player) => {
+					if let Err(err) = world.remove_player(&player) {
+						log.error("leave", json!({"player": player.0, "error": err.to_string()}));
+					}
+					message_cache.remove(&player);
+					empty_timer = 0;
+				}
+			}
+		}
+		if world.nplayers() == 0 && empty_timer > 100 {
+			if empty_timer == 600 {
+				world.reset();
+			}
+			continue;
+		}
+		world.update();
+		let messages = world.view();
+		for (player, mut message) in messages {
+			message_cache.trim(&player, &mut message);
+			if message.is_empty(){
+				continue;
+			}
+			if let Err(err) = gameserver.send(&player, message.to_json()) {
+				log.error("send", json!({"player": player.0, "error": err.to_string()}));
+			}
+		}
+		if let Some(duration) = scheduler.end_tick() {
+			log.warning("slow_tick", json!({
+				"duration_ms": duration.as_millis() as u64,
+				"step_duration_ms": step_duration.as_millis() as u64,
+				"overruns": scheduler.overruns
+			}));
+		}
+	}
+	log.info("shutdown", json!({}));
+}
+
+/** Do what the operator asked for. Returns the answer for the operator */
+fn run_command(command: ConsoleCommand, world: &mut World, gameserver: &mut GameServer, step_duration: &mut Duration, map_dir: Option<&Path>, access_file: Option<&Path>) -> Result<String> {
+	Ok(match command {
+		ConsoleCommand::Players => {
+			let players = gameserver.players();
+			let names: Vec<String> = players.iter()
+				.map(|player| match gameserver.peer(player) {
+					Some(Peer::Unknown) | None => player.to_string(),
+					Some(peer) => format!("{} ({})", player, peer)
+				})
+				.collect();
+			if names.is_empty() {
+				"nobody is connected".to_string()
+			} else {
+				format!("{} connected: {}", names.len(), names.join(", "))
+			}
+		}
+		ConsoleCommand::Kick(player, reason) => {
+			gameserver.kick(&player, reason.as_deref().unwrap_or("Kicked by the server operator"))
+				.map_err(|_| aerr!("{} is not connected", player))?;
+			format!("kicked {}", player)
+		}
+		ConsoleCommand::Ban(entry) => {
+			let name = entry.to_string();
+			if !gameserver.update_access(|access| access.ban(entry)) {
+				return Err(aerr!("{} was banned already", name));
+			}
+			save_access(gameserver, access_file)?;
+			format!("banned {}", name)
+		}
+		ConsoleCommand::Unban(entry) => {
+			if !gameserver.update_access(|access| access.unban(&entry)) {
+				return Err(aerr!("{} was not banned", entry));
+			}
+			save_access(gameserver, access_file)?;
+			format!("unbanned {}", entry)
+		}
+		ConsoleCommand::Allow(entry) => {
+			let name = entry.to_string();
+			if !gameserver.update_access(|access| access.allow(entry))? {
+				return Err(aerr!("{} was allowed already", name));
+			}
+			save_access(gameserver, access_file)?;
+			format!("allowed {}", name)
+		}
+		ConsoleCommand::Disallow(entry) => {
+			if !gameserver.update_access(|access| access.disallow(&entry)) {
+				return Err(aerr!("{} was not allowed", entry));
+			}
+			save_access(gameserver, access_file)?;
+			format!("disallowed {}", entry)
+		}
+		ConsoleCommand::Allowlist(on) => {
+			gameserver.update_access(|access| access.allowlist = on);
+			save_access(gameserver, access_file)?;
+			if on {
+				"only allowed players can join now".to_string()
+			} else {
+				"everyone who is not banned can join now".to_string()
+			}
+		}
+		ConsoleCommand::Register(player, password) => {
+			gameserver.accounts_mut().register(&player.0, &password)?;
+			format!("set the password of {}", player)
+		}
+		ConsoleCommand::Unregister(player) => {
+			if !gameserver.accounts_mut().unregister(&player.0)? {
+				return Err(aerr!("{} has no password", player));
+			}
+			format!("{} has no password anymore", player)
+		}
+		ConsoleCommand::Say(text) => {
+			gameserver.broadcast_announcement(&text);
+			"sent".to_string()
+		}
+		ConsoleCommand::Reset => {
+			world.reset();
+			now_playing(world)
+		}
+		ConsoleCommand::Map(name) => {
+			world.change_map(&name)?;
+			now_playing(world)
+		}
+		ConsoleCommand::Mode(gamemode) => {
+			world.change_gamemode(gamemode);
+			now_playing(world)
+		}
+		ConsoleCommand::StepDuration(milliseconds) => {
+			*step_duration = Duration::from_millis(milliseconds.max(1));
+			world.set_step_duration(*step_duration);
+			format!("the step duration is {}ms", step_duration.as_millis())
+		}
+		ConsoleCommand::Save(name) => {
+			let path = save_map(world, map_dir, &name)?;
+			format!("saved the map to {}", path.to_string_lossy())
+		}
+		ConsoleCommand::Help => CONSOLE_HELP.to_string()
+	})
+}
+
+fn round_rules(config: &Config) -> RoundRules {
+	RoundRules {
+		teams: config.teams,
+		score_limit: config.score_limit,
+		time_limit: Some(config.round_time)
+			.filter(|seconds| *seconds > 0)
+			.map(Duration::from_secs),
+		waves: config.survive_waves
+	}
+}
+
+/** Use the settings of a changed config file that can change while the server runs. Returns what to tell the admins */
+fn apply_config(config: Config, world: &mut World, gameserver: &mut GameServer, step_duration: &mut Duration) -> &'static str {
+	let rules = round_rules(&config).clamped();
+	gameserver.set_admins(config.admins);
+	gameserver.set_builders(config.builders);
+	world.set_min_players(config.bots);
+	*step_duration = Duration::from_millis(config.step_duration.max(1));
+	world.set_step_duration(*step_duration);
+	if *world.round_rules() == rules {
+		"The config file changed. The admins, builders, bots and step duration are updated"
+	} else if world.gamemode() == GameMode::Editor {
+		// a new round would throw away the changes to the map
+		world.queue_round_rules(rules);
+		"The config file changed. The admins, builders, bots and step duration are updated, and the new round rules are used from the next round on"
+	} else {
+		world.set_round_rules(rules);
+		"The config file changed. The admins, builders, bots and step duration are updated, and a new round started with the new round rules"
+	}
+}
+
+/** Keep the bans and the allowlist for the next time the server starts, if there is a file for them */
+fn save_access(gameserver: &GameServer, access_file: Option<&Path>) -> Result<()> {
+	match access_file {
+		Some(path) => gameserver.access().save(path),
+		None => Ok(())
+	}
+}
+
+fn now_playing(world: &World) -> String {
+	format!("now playing {} on {}", world.gamemode().name(), world.map_name())
+}
+
+fn update_metrics(metrics: &Mutex<Metrics>, world: &World, gameserver: &mut GameServer, adresses: &[Address], scheduler: &Scheduler, rounds: u64) {
+	let bytes_sent = gameserver.take_bytes_sent();
+	let Ok(mut metrics) = metrics.lock() else {
+		return;
+	};
+	metrics.tick_duration = scheduler.last_duration;
+	metrics.tick_overruns = scheduler.overruns;
+	metrics.players.clear();
+	for (address, nplayers) in adresses.iter().zip(gameserver.players_per_server()) {
+		*metrics.players.entry(address.kind()).or_insert(0) += nplayers;
+	}
+	metrics.creatures = world.creatures().count();
+	metrics.bullets = world.bullets().len();
+	metrics.bytes_sent = bytes_sent;
+	metrics.bytes_sent_total += bytes_sent as u64;
+	metrics.wave = world.wave();
+	metrics.rounds = rounds;
+}
+
+/** Write the map from the editor to the map directory, or to the current directory if there is none */
+fn save_map(world: &World, map_dir: Option<&Path>, name: &str) -> Result<PathBuf> {
+	let map = world.edited_map().ok_or_else(|| aerr!("maps can only be saved in the editor gamemode"))?;
+	if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+		return Err(aerr!("a map name can only have letters, numbers, '_' and '-'"));
+	}
+	let path = map_dir.unwrap_or_else(|| Path::new(".")).join(format!("{}.json5", name));
+	write_file_safe(&path, map_to_json5(map))?;
+	Ok(path)
+}
+
+/** Stop before starting the server when something is wrong with the configuration */
+fn exit_with(err: AnyError) -> ! {
+	println!("{}", err);
+	std::process::exit(1);
+}
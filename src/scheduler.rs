@@ -0,0 +1,83 @@
+
+use std::time::{Duration, Instant};
+
+/** Keeps ticks at a fixed rate by subtracting the time spent processing a tick from the time waited for the next */
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+	next_tick: Instant,
+	tick_start: Instant,
+	period: Duration,
+	pub last_duration: Duration,
+	pub overruns: usize
+}
+
+impl Scheduler {
+
+	pub fn new() -> Self {
+		let now = Instant::now();
+		Self {
+			next_tick: now,
+			tick_start: now,
+			period: Duration::from_millis(0),
+			last_duration: Duration::from_millis(0),
+			overruns: 0
+		}
+	}
+
+	/** How long until the next tick should start. Zero if it is due already */
+	pub fn time_left(&self) -> Duration {
+		self.next_tick.saturating_duration_since(Instant::now())
+	}
+
+	/** Mark the start of a tick and schedule the one after it */
+	pub fn start_tick(&mut self, period: Duration) {
+		let now = Instant::now();
+		let target = self.next_tick + period;
+		// when woken early or when more than a whole tick behind, start counting from now instead of catching up
+		self.next_tick = if self.next_tick > now || target <= now {
+			now + period
+		} else {
+			target
+		};
+		self.tick_start = now;
+		self.period = period;
+	}
+
+	/** Mark the end of a tick. Returns the time the tick took if that was longer than the tick period */
+	pub fn end_tick(&mut self) -> Option<Duration> {
+		self.last_duration = self.tick_start.elapsed();
+		if self.last_duration > self.period {
+			self.overruns += 1;
+			Some(self.last_duration)
+		} else {
+			None
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_tick_schedule_keeps_rate() {
+		let mut scheduler = Scheduler::new();
+		let start = scheduler.next_tick;
+		scheduler.start_tick(Duration::from_millis(100));
+		assert!(scheduler.next_tick >= start + Duration::from_millis(100));
+		assert!(scheduler.time_left() <= Duration::from_millis(100));
+		assert_eq!(scheduler.end_tick(), None);
+		assert_eq!(scheduler.overruns, 0);
+	}
+
+	#[test]
+	fn test_overrun_is_reported() {
+		let mut scheduler = Scheduler::new();
+		scheduler.start_tick(Duration::from_millis(0));
+		std::thread::sleep(Duration::from_millis(2));
+		assert!(scheduler.end_tick().is_some());
+		assert_eq!(scheduler.overruns, 1);
+		assert_eq!(scheduler.time_left(), Duration::from_millis(0));
+	}
+}
@@ -2,6 +2,7 @@
 use std::path::PathBuf;
 use std::net::SocketAddr;
 use std::str::FromStr;
+use std::fmt;
 use crate::{
 	Result,
 	aerr,
@@ -18,6 +19,16 @@ pub enum Address {
 }
 
 impl Address {
+	
+	/** The server type, as written before the colon */
+	pub fn kind(&self) -> &'static str {
+		match self {
+			Address::Inet(_) => "inet",
+			Address::Unix(path) if path.to_string_lossy().starts_with('\0') => "abstract",
+			Address::Unix(_) => "unix"
+		}
+	}
+	
 	pub fn to_server(&self) -> Result<Box<dyn Server>> {
 		match self {
 			Address::Inet(addr) => Ok(Box::new(TcpServer::new(addr)?)),
@@ -26,6 +37,22 @@ impl Address {
 	}
 }
 
+/** Written the same way as it is parsed */
+impl fmt::Display for Address {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Address::Inet(addr) => write!(f, "inet:{}", addr),
+			Address::Unix(path) => {
+				let path = path.to_string_lossy();
+				match path.strip_prefix('\0') {
+					Some(name) => write!(f, "abstract:{}", name),
+					None => write!(f, "unix:{}", path)
+				}
+			}
+		}
+	}
+}
+
 impl FromStr for Address {
 	type Err = AnyError;
 	fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
@@ -40,7 +67,7 @@ impl FromStr for Address {
 			"unix" => Ok(Address::Unix(PathBuf::new().join(text))),
 			"abstract" => {
 					if cfg!(target_os = "linux") {
-						Ok(Address::Unix(PathBuf::new().join(&format!("\0{}", text))))
+						Ok(Address::Unix(PathBuf::new().join(format!("\0{}", text))))
 					} else {
 						Err(aerr!("abstract adresses are only for linux"))
 					}
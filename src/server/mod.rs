@@ -1,4 +1,6 @@
 use std::io;
+use mio::{Poll, Token};
+use crate::access::{AccessList, Peer};
 
 pub mod tcpserver;
 pub mod unixserver;
@@ -6,18 +8,65 @@ pub mod address;
 
 mod streamconnection;
 
+const MAX_SERVERS: usize = 256;
+
+/** The poll token for the listener of a server */
+pub fn listener_token(serverid: usize) -> Token {
+	Token(serverid)
+}
+
+/** The poll token for a connection of a server */
+pub fn connection_token(serverid: usize, connectionid: usize) -> Token {
+	Token((connectionid + 1) * MAX_SERVERS + serverid)
+}
+
+/** The server and (if it's not the listener) the connection that a poll token belongs to */
+pub fn parse_token(token: Token) -> (usize, Option<usize>) {
+	let Token(n) = token;
+	(n % MAX_SERVERS, (n / MAX_SERVERS).checked_sub(1))
+}
+
+/** Whether an error from accepting only concerns one connection, so that the connections after it can still be accepted */
+fn is_connection_error(err: &io::Error) -> bool {
+	matches!(err.kind(), io::ErrorKind::ConnectionAborted | io::ErrorKind::ConnectionReset | io::ErrorKind::Interrupted)
+}
+
 
 pub trait Server {
 	
-	fn accept_pending_connections(&mut self) -> Vec<usize>;
+	fn register(&self, poll: &Poll, token: Token) -> Result<(), io::Error>;
 	
-	fn recv_pending_messages(&mut self) -> (Vec<(usize, String)>, Vec<usize>);
+	/** Accept the connections that are waiting, except those from peers the access list refuses. Errors that aren't about a single connection, like running out of file descriptors, leave the rest waiting */
+	fn accept_pending_connections(&mut self, poll: &Poll, serverid: usize, access: &AccessList) -> Vec<usize>;
+	
+	fn peer(&self, id: usize) -> Peer;
+	
+	fn recv_messages(&mut self, id: usize) -> Result<(Vec<String>, bool), io::Error>;
+	
+	fn flush(&mut self, id: usize) -> Result<(), io::Error>;
+	
+	fn remove(&mut self, id: usize);
 	
 	fn send(&mut self, id: usize, text: &str) -> Result<(), io::Error>;
 	
+	#[allow(dead_code)]
 	fn broadcast(&mut self, text: &str);
 	
+	/** The name of the local user on the other side, if the server can tell */
 	fn get_name(&self, _id: usize) -> Option<String> {
 		None
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn test_token_roundtrip() {
+		assert_eq!(parse_token(listener_token(3)), (3, None));
+		assert_eq!(parse_token(connection_token(3, 0)), (3, Some(0)));
+		assert_eq!(parse_token(connection_token(0, 41)), (0, Some(41)));
+	}
+}
+
@@ -2,12 +2,15 @@
 
 use std::io;
 use std::io::{Read, Write};
-use std::mem::transmute;
 
+const MAX_QUEUED_BYTES: usize = 1 << 22;
+/** Clients only send short messages, so anything longer is dropped together with its connection */
+const MAX_MESSAGE_BYTES: usize = 1 << 16;
 
 pub struct StreamConnection<T: Read+Write> {
 	pub stream: T,
-	buffer: Vec<u8>
+	buffer: Vec<u8>,
+	outbuffer: Vec<u8>
 }
 
 impl <T: Read+Write> StreamConnection<T> {
@@ -15,13 +18,15 @@ impl <T: Read+Write> StreamConnection<T> {
 	pub fn new(stream: T) -> StreamConnection<T> {
 		StreamConnection {
 			stream,
-			buffer: Vec::new()
+			buffer: Vec::new(),
+			outbuffer: Vec::new()
 		}
 	}
 	
 	pub fn read(&mut self) -> Result<(Vec<String>, bool), io::Error> {
 		let mut buf = [0; 2048];
 		let mut closed = false;
+		let mut messages = Vec::new();
 		loop {
 			match self.stream.read(&mut buf) {
 				Err(e) => {
@@ -37,15 +42,22 @@ impl <T: Read+Write> StreamConnection<T> {
 				}
 				Ok(i) => {
 					self.buffer.extend_from_slice(&buf[..i]);
-// 					messages.push(String::from_utf8_lossy(&buf[..i]).to_string());
+					// take the messages out as they complete, so the buffer never holds more than one
+					self.take_messages(&mut messages)?;
 				}
 			}
 		}
-		let mut messages = Vec::new();
+		Ok((messages, closed))
+	}
+	
+	fn take_messages(&mut self, messages: &mut Vec<String>) -> Result<(), io::Error> {
 		while self.buffer.len() >= 4 {
 			let mut header: [u8; 4] = [0;4];
 			header.copy_from_slice(&self.buffer[..4]);
-			let mlen: usize = u32::from_be(unsafe { transmute(header) }) as usize;
+			let mlen: usize = u32::from_be_bytes(header) as usize;
+			if mlen > MAX_MESSAGE_BYTES {
+				return Err(io::Error::new(io::ErrorKind::InvalidData, "message too long"));
+			}
 			if self.buffer.len() - 4 < mlen {
 				break;
 			}
@@ -54,15 +66,69 @@ impl <T: Read+Write> StreamConnection<T> {
 			messages.push(message);
 			self.buffer = rest;
 		}
-		Ok((messages, closed))
+		Ok(())
 	}
 	
+	/** Queue a message and write as much of the queue as the socket accepts without blocking */
 	pub fn send(&mut self, text: &str) -> Result<(), io::Error> {
 		let bytes: &[u8] = text.as_bytes();
+		if self.outbuffer.len() + bytes.len() > MAX_QUEUED_BYTES {
+			return Err(io::Error::other("too much data queued for this connection"));
+		}
 		let len: u32 = bytes.len() as u32;
-		let header: [u8; 4] = unsafe { transmute(len.to_be()) };
-		self.stream.write_all(&header)?;
-		self.stream.write_all(bytes)
+		self.outbuffer.extend_from_slice(&len.to_be_bytes());
+		self.outbuffer.extend_from_slice(bytes);
+		self.flush()
 	}
 	
+	/** Write queued data until the queue is empty or the socket would block */
+	pub fn flush(&mut self) -> Result<(), io::Error> {
+		let mut written = 0;
+		while written < self.outbuffer.len() {
+			match self.stream.write(&self.outbuffer[written..]) {
+				Ok(0) => {
+					return Err(io::Error::new(io::ErrorKind::WriteZero, "connection closed while writing"));
+				}
+				Ok(n) => {
+					written += n;
+				}
+				Err(e) => {
+					if e.kind() == io::ErrorKind::WouldBlock {
+						break;
+					} else {
+						return Err(e);
+					}
+				}
+			}
+		}
+		self.outbuffer.drain(..written);
+		Ok(())
+	}
+	
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Cursor;
+	
+	fn frame(text: &[u8], len: u32) -> Vec<u8> {
+		let mut bytes = len.to_be_bytes().to_vec();
+		bytes.extend_from_slice(text);
+		bytes
+	}
+	
+	#[test]
+	fn test_long_messages_are_refused() {
+		let mut bytes = frame(b"hello", 5);
+		bytes.extend(frame(b"world", 5));
+		let mut connection = StreamConnection::new(Cursor::new(bytes));
+		assert_eq!(connection.read().unwrap(), (vec!["hello".to_string(), "world".to_string()], true));
+		let mut connection = StreamConnection::new(Cursor::new(frame(b"", u32::MAX)));
+		assert!(connection.read().is_err());
+		let long = vec![b'a'; MAX_MESSAGE_BYTES + 1];
+		let mut connection = StreamConnection::new(Cursor::new(frame(&long, long.len() as u32)));
+		assert!(connection.read().is_err());
+	}
 }
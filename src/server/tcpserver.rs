@@ -4,9 +4,11 @@ use std::io;
 use std::net::SocketAddr;
 use mio::net::{TcpListener, TcpStream};
 use slab::Slab;
+use mio::{Poll, Token, Ready, PollOpt};
 
 use super::streamconnection::StreamConnection;
-use super::Server;
+use super::{Server, connection_token, is_connection_error};
+use crate::access::{AccessList, Peer};
 
 
 pub struct TcpServer {
@@ -27,47 +29,55 @@ impl TcpServer {
 
 impl Server for TcpServer {
 
-	fn accept_pending_connections(&mut self) -> Vec<usize> {
+	fn register(&self, poll: &Poll, token: Token) -> Result<(), io::Error> {
+		poll.register(&self.listener, token, Ready::readable(), PollOpt::edge())
+	}
+
+	fn accept_pending_connections(&mut self, poll: &Poll, serverid: usize, access: &AccessList) -> Vec<usize> {
 		let mut new_connections = Vec::new();
 		loop {
-			match self.listener.accept() {
-				Err(_e) => {
-					break;
-				}
-				Ok((stream, _address)) => {
-					let con = StreamConnection::new(stream);
-					let id = self.connections.insert(con);
-					new_connections.push(id);
-				}
+			let (stream, address) = match self.listener.accept() {
+				Ok(accepted) => accepted,
+				Err(err) if is_connection_error(&err) => continue,
+				Err(_) => break
+			};
+			if !access.allows_peer(&Peer::Ip(address.ip())) {
+				continue;
 			}
+			let entry = self.connections.vacant_entry();
+			let id = entry.key();
+			if poll.register(&stream, connection_token(serverid, id), Ready::readable() | Ready::writable(), PollOpt::edge()).is_err() {
+				continue;
+			}
+			entry.insert(StreamConnection::new(stream));
+			new_connections.push(id);
 		}
 		new_connections
 	}
 
 
-	fn recv_pending_messages(&mut self) -> (Vec<(usize, String)>, Vec<usize>){
-	// 	let mut buf = [0; 2048];
-		let mut messages: Vec<(usize, String)> = Vec::new();
-		let mut to_remove = Vec::new();
-		for (key, connection) in self.connections.iter_mut(){
-			match connection.read() {
-				Err(_e) => {
-					to_remove.push(key);
-				}
-				Ok((con_messages, closed)) => {
-					for message in con_messages {
-						messages.push((key, message));
-					}
-					if closed {
-						to_remove.push(key);
-					}
-				}
+	fn recv_messages(&mut self, id: usize) -> Result<(Vec<String>, bool), io::Error> {
+		match self.connections.get_mut(id){
+			Some(conn) => {
+				conn.read()
 			}
+			None => Err(io::Error::other("index is empty"))
 		}
-		for key in to_remove.iter() {
-			self.connections.remove(*key);
+	}
+
+	fn flush(&mut self, id: usize) -> Result<(), io::Error> {
+		match self.connections.get_mut(id){
+			Some(conn) => {
+				conn.flush()
+			}
+			None => Err(io::Error::other("index is empty"))
+		}
+	}
+
+	fn remove(&mut self, id: usize) {
+		if self.connections.contains(id) {
+			self.connections.remove(id);
 		}
-		(messages, to_remove)
 	}
 
 	fn broadcast(&mut self, text: &str) {
@@ -81,10 +91,14 @@ impl Server for TcpServer {
 			Some(conn) => {
 				conn.send(text)
 			}
-			None => Err(io::Error::new(io::ErrorKind::Other, "index is empty"))
+			None => Err(io::Error::other("index is empty"))
 		}
 	}
 	
-
+	fn peer(&self, id: usize) -> Peer {
+		self.connections.get(id)
+			.and_then(|connection| connection.stream.peer_addr().ok())
+			.map_or(Peer::Unknown, |address| Peer::Ip(address.ip()))
+	}
 }
 
@@ -5,11 +5,13 @@ use std::path::Path;
 use std::os::unix::io::AsRawFd;
 use mio_uds::{UnixListener, UnixStream};
 use slab::Slab;
+use mio::{Poll, Token, Ready, PollOpt};
 use nix::sys::socket::getsockopt;
 use nix::sys::socket::sockopt;
 
 use super::streamconnection::StreamConnection;
-use super::Server;
+use super::{Server, connection_token, is_connection_error};
+use crate::access::{AccessList, Peer};
 
 
 pub struct UnixServer {
@@ -32,19 +34,33 @@ impl UnixServer {
 
 impl Server for UnixServer {
 
-	fn accept_pending_connections(&mut self) -> Vec<usize> {
+	fn register(&self, poll: &Poll, token: Token) -> Result<(), io::Error> {
+		poll.register(&self.listener, token, Ready::readable(), PollOpt::edge())
+	}
+
+	fn accept_pending_connections(&mut self, poll: &Poll, serverid: usize, access: &AccessList) -> Vec<usize> {
 		let mut new_connections = Vec::new();
 		loop {
 			match self.listener.accept() {
 				Ok(Some((stream, _address))) => {
-					let con = StreamConnection::new(stream);
-					let id = self.connections.insert(con);
+					if !access.allows_peer(&stream_peer(&stream)) {
+						continue;
+					}
+					let entry = self.connections.vacant_entry();
+					let id = entry.key();
+					if poll.register(&stream, connection_token(serverid, id), Ready::readable() | Ready::writable(), PollOpt::edge()).is_err() {
+						continue;
+					}
+					entry.insert(StreamConnection::new(stream));
 					new_connections.push(id);
 				}
 				Ok(None) => {
 					break;
 				}
-				Err(_e) => {
+				Err(err) if is_connection_error(&err) => {
+					continue;
+				}
+				Err(_) => {
 					break;
 				}
 			}
@@ -53,29 +69,28 @@ impl Server for UnixServer {
 	}
 
 
-	fn recv_pending_messages(&mut self) -> (Vec<(usize, String)>, Vec<usize>){
-	// 	let mut buf = [0; 2048];
-		let mut messages: Vec<(usize, String)> = Vec::new();
-		let mut to_remove = Vec::new();
-		for (key, connection) in self.connections.iter_mut(){
-			match connection.read() {
-				Err(_e) => {
-					to_remove.push(key);
-				}
-				Ok((con_messages, closed)) => {
-					for message in con_messages {
-						messages.push((key, message));
-					}
-					if closed {
-						to_remove.push(key);
-					}
-				}
+	fn recv_messages(&mut self, id: usize) -> Result<(Vec<String>, bool), io::Error> {
+		match self.connections.get_mut(id){
+			Some(conn) => {
+				conn.read()
+			}
+			None => Err(io::Error::other("index is empty"))
+		}
+	}
+
+	fn flush(&mut self, id: usize) -> Result<(), io::Error> {
+		match self.connections.get_mut(id){
+			Some(conn) => {
+				conn.flush()
 			}
+			None => Err(io::Error::other("index is empty"))
 		}
-		for key in to_remove.iter() {
-			self.connections.remove(*key);
+	}
+
+	fn remove(&mut self, id: usize) {
+		if self.connections.contains(id) {
+			self.connections.remove(id);
 		}
-		(messages, to_remove)
 	}
 
 	fn broadcast(&mut self, text: &str) {
@@ -89,16 +104,19 @@ impl Server for UnixServer {
 			Some(conn) => {
 				conn.send(text)
 			}
-			None => Err(io::Error::new(io::ErrorKind::Other, "index is empty"))
+			None => Err(io::Error::other("index is empty"))
 		}
 	}
 	
+	fn peer(&self, id: usize) -> Peer {
+		self.connections.get(id).map_or(Peer::Unknown, |connection| stream_peer(&connection.stream))
+	}
+	
 	#[cfg(any(target_os = "linux", target_os = "android"))]
 	fn get_name(&self, id: usize) -> Option<String> {
-		let connection = self.connections.get(id)?;
-		let fd = connection.stream.as_raw_fd();
-		let peercred = getsockopt(fd, sockopt::PeerCredentials).ok()?;
-		let uid = peercred.uid();
+		let Peer::Uid(uid) = self.peer(id) else {
+			return None;
+		};
 		let user = users::get_user_by_uid(uid)?;
 		let name = user.name();
 		Some(name.to_string_lossy().to_string())
@@ -110,3 +128,14 @@ impl Server for UnixServer {
 	}
 }
 
+/** The user on the other side of the socket */
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn stream_peer(stream: &UnixStream) -> Peer {
+	getsockopt(stream.as_raw_fd(), sockopt::PeerCredentials)
+		.map_or(Peer::Unknown, |peercred| Peer::Uid(peercred.uid()))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn stream_peer(_stream: &UnixStream) -> Peer {
+	Peer::Unknown
+}
@@ -0,0 +1,161 @@
+
+use std::collections::HashMap;
+use crate::{
+	Pos,
+	grid::Grid,
+	pos::Distance
+};
+
+const CHUNK_SIZE: i64 = 8;
+
+/** Keeps track of where creatures are, so lookups by position or by area don't have to scan all creatures */
+#[derive(Debug, Clone)]
+pub struct SpatialIndex {
+	occupants: HashMap<Pos, Vec<usize>>,
+	chunks: Grid<Vec<(usize, Pos)>>,
+	nchunks: Pos
+}
+
+impl SpatialIndex {
+
+	pub fn new(size: Pos) -> Self {
+		let nchunks = Pos::new(
+			(size.x + CHUNK_SIZE - 1) / CHUNK_SIZE,
+			(size.y + CHUNK_SIZE - 1) / CHUNK_SIZE
+		);
+		Self {
+			occupants: HashMap::new(),
+			chunks: Grid::new(nchunks, Vec::new()),
+			nchunks
+		}
+	}
+
+	fn chunk_of(pos: Pos) -> Pos {
+		Pos::new(pos.x.div_euclid(CHUNK_SIZE), pos.y.div_euclid(CHUNK_SIZE))
+	}
+
+	pub fn insert(&mut self, id: usize, pos: Pos) {
+		self.occupants.entry(pos).or_default().push(id);
+		if let Some(chunk) = self.chunks.get_mut(Self::chunk_of(pos)) {
+			chunk.push((id, pos));
+		}
+	}
+
+	pub fn remove(&mut self, id: usize, pos: Pos) {
+		if let Some(ids) = self.occupants.get_mut(&pos) {
+			ids.retain(|i| *i != id);
+			if ids.is_empty() {
+				self.occupants.remove(&pos);
+			}
+		}
+		if let Some(chunk) = self.chunks.get_mut(Self::chunk_of(pos)) {
+			chunk.retain(|(i, _)| *i != id);
+		}
+	}
+
+	pub fn move_to(&mut self, id: usize, from: Pos, to: Pos) {
+		self.remove(id, from);
+		self.insert(id, to);
+	}
+
+	pub fn at(&self, pos: Pos) -> &[usize] {
+		self.occupants.get(&pos).map(Vec::as_slice).unwrap_or(&[])
+	}
+
+	pub fn is_occupied(&self, pos: Pos) -> bool {
+		self.occupants.contains_key(&pos)
+	}
+
+	/** All creatures in the chunks that are exactly `ring` chunks away from `center` */
+	fn ring(&self, center: Pos, ring: i64) -> impl Iterator<Item=&(usize, Pos)> {
+		let cx = center.x;
+		let cy = center.y;
+		((cy - ring)..=(cy + ring))
+			.flat_map(move |y| ((cx - ring)..=(cx + ring)).map(move |x| Pos::new(x, y)))
+			.filter(move |c| (c.x - cx).abs() == ring || (c.y - cy).abs() == ring)
+			.filter_map(move |c| self.chunks.get(c))
+			.flatten()
+	}
+
+	fn max_ring(&self, center: Pos) -> i64 {
+		[center.x, center.y, self.nchunks.x - 1 - center.x, self.nchunks.y - 1 - center.y]
+			.iter()
+			.cloned()
+			.max()
+			.unwrap_or(0)
+	}
+
+	/** The nearest creature for which the predicate holds */
+	pub fn nearest<F>(&self, pos: Pos, is_target: F) -> Option<(usize, Pos)>
+			where F: Fn(usize) -> bool {
+		let center = Self::chunk_of(pos);
+		let mut best: Option<(usize, Pos)> = None;
+		for ring in 0..=self.max_ring(center) {
+			for (id, target_pos) in self.ring(center, ring) {
+				if best.is_none_or(|(_, best_pos)| pos.distance_to(*target_pos) < pos.distance_to(best_pos)) && is_target(*id) {
+					best = Some((*id, *target_pos));
+				}
+			}
+			// anything in the next ring is at least this far away
+			if let Some((_, best_pos)) = best {
+				if pos.distance_to(best_pos) <= Distance(ring * CHUNK_SIZE) {
+					break;
+				}
+			}
+		}
+		best
+	}
+
+	/** All creatures within the given distance of pos */
+	pub fn in_radius(&self, pos: Pos, radius: Distance) -> Vec<(usize, Pos)> {
+		let center = Self::chunk_of(pos);
+		let rings = (radius.0 + CHUNK_SIZE - 1) / CHUNK_SIZE;
+		(0..=rings)
+			.flat_map(|ring| self.ring(center, ring))
+			.filter(|(_, p)| pos.distance_to(*p) <= radius)
+			.cloned()
+			.collect()
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_nearest_crosses_chunks() {
+		let mut index = SpatialIndex::new(Pos::new(64, 64));
+		index.insert(1, Pos::new(30, 30));
+		index.insert(2, Pos::new(8, 8));
+		index.insert(3, Pos::new(62, 1));
+		assert_eq!(index.nearest(Pos::new(7, 7), |_| true), Some((2, Pos::new(8, 8))));
+		assert_eq!(index.nearest(Pos::new(7, 7), |id| id != 2), Some((1, Pos::new(30, 30))));
+		assert_eq!(index.nearest(Pos::new(7, 7), |id| id == 3), Some((3, Pos::new(62, 1))));
+		assert_eq!(index.nearest(Pos::new(7, 7), |_| false), None);
+	}
+
+	#[test]
+	fn test_move_and_remove() {
+		let mut index = SpatialIndex::new(Pos::new(20, 20));
+		index.insert(1, Pos::new(3, 3));
+		index.insert(2, Pos::new(3, 3));
+		index.move_to(1, Pos::new(3, 3), Pos::new(12, 3));
+		assert_eq!(index.at(Pos::new(3, 3)), &[2]);
+		assert_eq!(index.at(Pos::new(12, 3)), &[1]);
+		index.remove(2, Pos::new(3, 3));
+		assert!(!index.is_occupied(Pos::new(3, 3)));
+		assert_eq!(index.nearest(Pos::new(0, 0), |_| true), Some((1, Pos::new(12, 3))));
+	}
+
+	#[test]
+	fn test_in_radius() {
+		let mut index = SpatialIndex::new(Pos::new(32, 32));
+		index.insert(1, Pos::new(10, 10));
+		index.insert(2, Pos::new(13, 12));
+		index.insert(3, Pos::new(20, 20));
+		let mut found: Vec<usize> = index.in_radius(Pos::new(10, 10), Distance(5)).into_iter().map(|(id, _)| id).collect();
+		found.sort();
+		assert_eq!(found, vec![1, 2]);
+	}
+}
@@ -10,7 +10,7 @@ pub enum Sprite {
 	Player(&'static str, char)
 }
 
-const VALID_COLOURS: &'static[&'static str] = &["r", "g", "b", "c", "m", "y", "lr", "lg", "lb", "lc", "lm", "ly", "a"];
+const VALID_COLOURS: &[&str] = &["r", "g", "b", "c", "m", "y", "lr", "lg", "lb", "lc", "lm", "ly", "a"];
 
 impl Sprite {
 	
@@ -0,0 +1,98 @@
+
+use crate::{
+	Pos,
+	sprite::Sprite
+};
+
+const TEAMS: &[(&str, &str, &str, &str)] = &[
+	("red", "r", "flag_red", "zone_red"),
+	("blue", "b", "flag_blue", "zone_blue"),
+	("green", "g", "flag_green", "zone_green"),
+	("yellow", "y", "flag_yellow", "zone_yellow"),
+	("magenta", "m", "flag_magenta", "zone_magenta"),
+	("cyan", "c", "flag_cyan", "zone_cyan")
+];
+
+pub const MAX_TEAMS: usize = TEAMS.len();
+
+/** How rounds are played and when they end */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoundRules {
+	pub teams: usize,
+	/** Defaults to the usual limit of the gamemode. 0 means no limit */
+	pub score_limit: Option<u64>,
+	/** In game time: every step counts for the step duration it was played with */
+	pub time_limit: Option<std::time::Duration>,
+	/** Win co-op rounds after surviving this many waves, instead of what the map says */
+	pub waves: Option<usize>
+}
+
+impl RoundRules {
+	/** The same rules with a number of teams that can be played */
+	pub fn clamped(self) -> Self {
+		Self {
+			teams: self.teams.clamp(1, MAX_TEAMS),
+			..self
+		}
+	}
+}
+
+impl Default for RoundRules {
+	fn default() -> Self {
+		Self {
+			teams: 2,
+			score_limit: None,
+			time_limit: None,
+			waves: None
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Team {
+	pub spawnpoint: Pos,
+	pub score: u64,
+	/** Where the flag of this team belongs in capture the flag */
+	pub flag: Option<Pos>,
+	/** Where this team brings captured flags */
+	pub base: Option<Pos>
+}
+
+pub fn team_name(team: usize) -> &'static str {
+	TEAMS[team % MAX_TEAMS].0
+}
+
+pub fn flag_sprite(team: usize) -> &'static str {
+	TEAMS[team % MAX_TEAMS].2
+}
+
+pub fn zone_sprite(team: usize) -> &'static str {
+	TEAMS[team % MAX_TEAMS].3
+}
+
+/** The sprite of a player recoloured to the colour of their team */
+pub fn team_sprite(team: usize, sprite: Sprite) -> Sprite {
+	match sprite {
+		Sprite::Player(_, letter) => Sprite::Player(TEAMS[team % MAX_TEAMS].1, letter),
+		other => other
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_team_sprite_keeps_letter() {
+		assert_eq!(team_sprite(1, Sprite::Player("lg", 'a')), Sprite::Player("b", 'a'));
+		assert_eq!(team_sprite(MAX_TEAMS, Sprite::Player("a", 'q')), Sprite::Player("r", 'q'));
+	}
+
+	#[test]
+	fn test_clamped_rules_have_playable_teams() {
+		assert_eq!(RoundRules {teams: 0, ..RoundRules::default()}.clamped().teams, 1);
+		assert_eq!(RoundRules {teams: MAX_TEAMS + 3, ..RoundRules::default()}.clamped().teams, MAX_TEAMS);
+		assert_eq!(RoundRules::default().clamped(), RoundRules::default());
+	}
+}
@@ -3,7 +3,6 @@ use crate::Sprite;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FloorType{
-	#[allow(dead_code)]
 	Stone,
 	Dirt,
 	Grass1,
@@ -15,7 +14,6 @@ pub enum FloorType{
 pub enum WallType{
 	Wall,
 	Rubble,
-	#[allow(dead_code)]
 	Rock
 }
 
@@ -70,6 +68,41 @@ impl Tile {
 		}
 	}
 	
+	/** The tile with this sprite name */
+	pub fn from_name(name: &str) -> Option<Self> {
+		Some(match name {
+			"floor" => Tile::Floor(FloorType::Stone),
+			"ground" => Tile::Floor(FloorType::Dirt),
+			"grass1" => Tile::Floor(FloorType::Grass1),
+			"grass2" => Tile::Floor(FloorType::Grass2),
+			"grass3" => Tile::Floor(FloorType::Grass3),
+			"gate" => Tile::Gate,
+			"sanctuary" => Tile::Sanctuary,
+			"wall" => Tile::Wall(WallType::Wall),
+			"rubble" => Tile::Wall(WallType::Rubble),
+			"rock" => Tile::Wall(WallType::Rock),
+			"water" => Tile::Obstacle(ObstacleType::Water),
+			_ => {return None}
+		})
+	}
+	
+	/** The character for this tile in map files */
+	pub fn to_char(&self) -> char {
+		match self {
+			Tile::Floor(FloorType::Stone) => '"',
+			Tile::Floor(FloorType::Dirt) => '.',
+			Tile::Floor(FloorType::Grass1) => ',',
+			Tile::Floor(FloorType::Grass2) => '\'',
+			Tile::Floor(FloorType::Grass3) => '`',
+			Tile::Gate => '=',
+			Tile::Sanctuary => '+',
+			Tile::Wall(WallType::Wall) => '#',
+			Tile::Wall(WallType::Rock) => 'X',
+			Tile::Wall(WallType::Rubble) => 'R',
+			Tile::Obstacle(ObstacleType::Water) => '~'
+		}
+	}
+	
 	pub fn from_char(c: char) -> Option<Self>{
 		Some(match c {
 			'"' => Tile::Floor(FloorType::Stone),
@@ -1,5 +1,7 @@
 
 use std::cmp::{min, max};
+use std::cell::RefCell;
+use rand::{Rng, RngCore, SeedableRng, rngs::StdRng, distributions::{Distribution, Standard}};
 
 
 pub fn clamp<T: Ord>(val: T, lower: T, upper: T) -> T{
@@ -29,7 +31,6 @@ pub fn randomize (mut seed: u32) -> u32 {
 	seed
 }
 
-#[allow(dead_code)]
 pub fn partition_by(s: &str, pat: &str) -> (String, String) {
 	let mut parts: Vec<String> = s.splitn(2, pat).map(String::from).collect();
 	while parts.len() < 2 {
@@ -39,7 +40,6 @@ pub fn partition_by(s: &str, pat: &str) -> (String, String) {
 }
 
 
-#[allow(dead_code)]
 pub fn write_file_safe<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> Result<(), AnyError> {
 	let temppath = path
 		.as_ref()
@@ -71,6 +71,42 @@ macro_rules! hashmap {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Percentage(pub i64);
 
+thread_local! {
+	static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+/** Reseed the random generator of this thread, to make games reproducible */
+pub fn seed_rng(seed: u64) {
+	RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/** Like rand::thread_rng, but it can be seeded with seed_rng */
+#[derive(Debug, Clone, Copy)]
+pub struct GameRng;
+
+impl RngCore for GameRng {
+	fn next_u32(&mut self) -> u32 {
+		RNG.with(|rng| rng.borrow_mut().next_u32())
+	}
+	fn next_u64(&mut self) -> u64 {
+		RNG.with(|rng| rng.borrow_mut().next_u64())
+	}
+	fn fill_bytes(&mut self, dest: &mut [u8]) {
+		RNG.with(|rng| rng.borrow_mut().fill_bytes(dest))
+	}
+	fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+		RNG.with(|rng| rng.borrow_mut().try_fill_bytes(dest))
+	}
+}
+
+pub fn rng() -> GameRng {
+	GameRng
+}
+
+pub fn random<T>() -> T where Standard: Distribution<T> {
+	rng().gen()
+}
+
 pub struct Tuple2;
 
 #[allow(dead_code)]
@@ -89,6 +125,15 @@ impl Tuple2 {
 mod tests {
 	use std::collections::HashMap;
 	#[test]
+	fn test_seeded_rng_repeats() {
+		use rand::Rng;
+		super::seed_rng(42);
+		let first: Vec<u32> = (0..5).map(|_| super::rng().gen_range(0..1000)).collect();
+		super::seed_rng(42);
+		let second: Vec<u32> = (0..5).map(|_| super::rng().gen_range(0..1000)).collect();
+		assert_eq!(first, second);
+	}
+	#[test]
 	fn test_hashmap_macro() {
 		let mut h = hashmap!("hello" => 1, "world" => 2);
 		assert_eq!(h.remove("hello"), Some(1));
@@ -1,11 +1,11 @@
 
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
 use crate::{
 	sprite::Sprite,
 	Pos,
 	creature::{Alignment, Health},
-	util::Percentage,
+	util::{Percentage, rng, random},
 	timestamp::Duration,
 	pos::Distance
 };
@@ -24,7 +24,7 @@ impl Weapon {
 
 	
 	pub fn shoot(&self, pos: Pos, direction: Pos, alignment: Alignment) -> Vec<Bullet> {
-		let mut rng = thread_rng();
+		let mut rng = rng();
 		let deviation = self.spread.0 * direction.size().0;
 		(0..self.nbullets)
 			.map(|_| {
@@ -183,8 +183,8 @@ impl Bullet {
 	
 	fn inaccurate_movement(&self) -> Pos {
 		/* sometimes move sideways to simulate inaccuracy */
-		if self.steps.size() == Distance(1) && rand::random() {
-			let r = if rand::random() { 1 } else { -1 };
+		if self.steps.size() == Distance(1) && random() {
+			let r = if random() { 1 } else { -1 };
 			if self.direction.y.abs() > self.direction.x.abs() {
 				Pos::new(r, 0)
 			} else {
@@ -223,7 +223,7 @@ impl Bullet {
 
 
 fn quadrant_move_y(dir: Pos, steps: Pos) -> bool {
-	if dir.y > dir.x || dir.x == dir.y && rand::random() { 
+	if dir.y > dir.x || dir.x == dir.y && random() { 
 		!octant_move_y(Pos::new(dir.y, dir.x), Pos::new(steps.y, steps.x))
 	} else {
 		octant_move_y(dir, steps)
@@ -1,6 +1,7 @@
 
-use std::collections::{HashMap, VecDeque};
-use rand::{Rng, thread_rng, seq::SliceRandom};
+use std::collections::{HashMap, HashSet, BTreeMap, VecDeque, hash_map::Entry};
+use std::time::Instant;
+use rand::{Rng, seq::SliceRandom};
 
 use crate::{
 	PlayerId,
@@ -11,7 +12,7 @@ use crate::{
 	Direction,
 	holder::Holder,
 	sprite::Sprite,
-	worldmessages::{WorldMessage, FieldMessage, ChangeMessage},
+	worldmessages::{WorldMessage, FieldMessage, ChangeMessage, SoundMessage},
 	timestamp::{Timestamp, Duration},
 	creature::{Creature, Mind, CreatureType, Alignment, Health},
 	tile::Tile,
@@ -20,14 +21,38 @@ use crate::{
 	player::Player,
 	waves::wave_composition,
 	gamemode::GameMode,
-	mapgen::{MapTemplate, MapType, create_map},
+	mapgen::{MapTemplate, MapType, MonsterSpawn, SpawnZone, create_map, trace_path},
+	rotation::{Rotation, Vote, most_voted},
 	grid::Grid,
 	pos::Distance,
-	util::Percentage
+	util::{Percentage, rng},
+	spatialindex::SpatialIndex,
+	team::{Team, RoundRules, team_name, team_sprite, zone_sprite},
+	zone::Zone,
+	editor::Editor,
+	eventlog::GameEvent
 };
 
+const BOT_RETREAT_HEALTH: Percentage = Percentage(40);
+const BOT_DEVIATION: Percentage = Percentage(10);
+const GAME_OVER_DURATION: Duration = Duration(50);
+const GAME_OVER_TEXT: &str = "GAME_OVER!";
+const DEFAULT_STEP_DURATION: std::time::Duration = std::time::Duration::from_millis(100);
+/** How close a player has to be for the payload to move */
+const ESCORT_DISTANCE: Distance = Distance(4);
+/** Events that nobody takes are dropped after this many, oldest first */
+const MAX_EVENTS: usize = 1000;
+
+/** Time spent in each phase of the world update, summed over all updates */
+#[derive(Debug, Default, Clone)]
+pub struct PhaseTimes {
+	pub update_creatures: std::time::Duration,
+	pub update_bullets: std::time::Duration,
+	pub spawn: std::time::Duration
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum RoundState {
+pub enum RoundState {
 	Running,
 	GameOver(Duration),
 	Paused(Duration)
@@ -43,52 +68,121 @@ pub struct World {
 	time: Timestamp,
 	size: Pos,
 	ground: Grid<Tile>,
-	players: HashMap<PlayerId, Player>,
+	players: BTreeMap<PlayerId, Player>,
 	creatures: Holder<Creature>,
+	creature_index: SpatialIndex,
 	bullets: Vec<Bullet>,
 	particles: HashMap<Pos, Sprite>,
 	spawnpoint: Pos,
-	monsterspawn: Vec<Pos>,
+	monsterspawn: Vec<MonsterSpawn>,
+	teamspawns: Vec<Pos>,
+	spawnzones: Vec<SpawnZone>,
+	teams: Vec<Team>,
+	rules: RoundRules,
+	/** How long the current round has been played, counted in steps of the step duration */
+	round_time: std::time::Duration,
+	step_duration: std::time::Duration,
+	zones: Vec<Zone>,
+	coop_score: u64,
+	payload_route: Vec<Pos>,
+	payload_progress: usize,
 	items: HashMap<Pos, Item>,
 	wave: usize,
 	to_spawn: Vec<CreatureType>,
 	round_state: RoundState,
+	/** The gamemode of this round */
 	gamemode: GameMode,
-	map: MapType,
+	/** The gamemode the server was set to or that was voted for. Maps that aren't made for it are played in a gamemode they are made for */
+	chosen_gamemode: GameMode,
+	rotation: Rotation,
+	/** Maps that were changed while a round was going on */
+	pending_rotation: Option<Rotation>,
+	pending_rules: Option<RoundRules>,
+	/** The map that is being changed in the editor gamemode */
+	editor: Option<Editor>,
+	survive_waves: Option<usize>,
+	round_won: bool,
+	monsters_killed: u64,
+	deaths: u64,
 	building_distances: Grid<Option<usize>>,
 	player_distances: Grid<Option<usize>>,
+	monster_distances: Grid<Option<usize>>,
+	sanctuary_owners: HashMap<Pos, usize>,
+	sanctuary_distances: Vec<Grid<Option<usize>>>,
+	spawn_distances: Vec<Grid<Option<usize>>>,
+	base_distances: Vec<Grid<Option<usize>>>,
+	flag_distances: Vec<Grid<Option<usize>>>,
+	zone_distances: Vec<Grid<Option<usize>>>,
+	announcements: Vec<String>,
+	events: Vec<GameEvent>,
+	min_players: usize,
 	drawing: Option<HashMap<Pos, Vec<Sprite>>>,
+	phase_times: PhaseTimes,
 }
 
 impl World {
 	
+	/** Create a world for the given map and game mode. It starts without any players */
 	pub fn new(gamemode: GameMode, map: MapType) -> Self {
 		
 		let mut world = World {
 			size: Pos::new(0, 0),
 			spawnpoint: Pos::new(0, 0),
 			ground: Grid::empty(),
-			players: HashMap::new(),
+			players: BTreeMap::new(),
 			creatures: Holder::new(),
+			creature_index: SpatialIndex::new(Pos::new(0, 0)),
 			bullets: Vec::new(),
 			time: Timestamp(0),
 			particles: HashMap::new(),
 			monsterspawn: Vec::new(),
+			teamspawns: Vec::new(),
+			spawnzones: Vec::new(),
+			teams: Vec::new(),
+			rules: RoundRules::default(),
+			round_time: std::time::Duration::ZERO,
+			step_duration: DEFAULT_STEP_DURATION,
+			zones: Vec::new(),
+			coop_score: 0,
+			payload_route: Vec::new(),
+			payload_progress: 0,
 			items: HashMap::new(),
 			wave: 0,
 			to_spawn: Vec::new(),
 			round_state: RoundState::Running,
 			gamemode,
-			map,
+			chosen_gamemode: gamemode,
+			rotation: Rotation::new(vec![(map.name().to_string(), map)]).unwrap(),
+			pending_rotation: None,
+			pending_rules: None,
+			editor: None,
+			survive_waves: None,
+			round_won: false,
+			monsters_killed: 0,
+			deaths: 0,
 			building_distances: Grid::empty(),
 			player_distances: Grid::empty(),
+			monster_distances: Grid::empty(),
+			sanctuary_owners: HashMap::new(),
+			sanctuary_distances: Vec::new(),
+			spawn_distances: Vec::new(),
+			base_distances: Vec::new(),
+			flag_distances: Vec::new(),
+			zone_distances: Vec::new(),
+			announcements: Vec::new(),
+			events: Vec::new(),
+			min_players: 0,
 			drawing: None,
+			phase_times: PhaseTimes::default(),
 		};
 		world.reset();
 		world
 	}
 	
+	/** Start a new round on a fresh copy of the map. Players stay, but their bodies are removed */
 	pub fn reset(&mut self) {
+		self.apply_pending_rotation();
+		self.apply_pending_rules();
 		self.creatures.clear();
 		self.bullets.clear();
 		self.particles.clear();
@@ -96,49 +190,351 @@ impl World {
 		self.wave = 0;
 		self.to_spawn.clear();
 		self.round_state = RoundState::Running;
-		let template: MapTemplate = create_map(&self.map, self.gamemode);
+		self.round_time = std::time::Duration::ZERO;
+		self.round_won = false;
+		self.monsters_killed = 0;
+		self.deaths = 0;
+		self.gamemode = self.rotation.current().playable_gamemode(self.chosen_gamemode);
+		let template: MapTemplate = create_map(self.rotation.current(), self.gamemode);
+		self.editor = if self.gamemode == GameMode::Editor {
+			Some(Editor::new(template.clone()))
+		} else {
+			None
+		};
+		self.survive_waves = self.rules.waves.or(template.survive_waves);
 		self.size = template.size;
 		self.ground = template.ground;
 		self.spawnpoint = template.spawnpoint;
 		self.monsterspawn = template.monsterspawn;
-		for (pos, creature) in template.creatures {
-			self.creatures.insert(Creature::create_creature(creature, pos));
+		self.teamspawns = template.teamspawns;
+		self.spawnzones = template.spawnzones;
+		let flags = template.flags;
+		let bases = template.bases;
+		self.teams = (0..self.rules.teams)
+			.map(|index| Team {
+				spawnpoint: if self.teamspawns.is_empty() {
+					self.spawnzones.iter()
+						.find(|zone| zone.team == Some(index))
+						.map(|zone| zone.from)
+						.unwrap_or(self.spawnpoint)
+				} else {
+					self.teamspawns[index % self.teamspawns.len()]
+				},
+				score: 0,
+				flag: flags.get(index).cloned(),
+				base: bases.get(index).or(flags.get(index)).cloned()
+			})
+			.collect();
+		if self.gamemode == GameMode::CaptureTheFlag {
+			for (index, team) in self.teams.iter().enumerate() {
+				if let Some(flag) = team.flag {
+					self.items.insert(flag, Item::Flag(index));
+				}
+			}
+		}
+		self.zones = template.zones.iter()
+			.map(|(center, radius)| Zone::new(*center, Distance(*radius)))
+			.collect();
+		self.coop_score = 0;
+		self.compute_zone_distances();
+		self.payload_route = if self.gamemode == GameMode::Escort {
+			trace_path(&template.payload_path)
+		} else {
+			Vec::new()
+		};
+		self.payload_progress = 0;
+		self.announcements.clear();
+		self.announcements.push(match &template.author {
+			Some(author) => format!("Now playing {} on {} by {}", self.gamemode.name(), self.rotation.current_name(), author),
+			None => format!("Now playing {} on {}", self.gamemode.name(), self.rotation.current_name())
+		});
+		self.announcements.extend(template.description);
+		self.events.push(GameEvent::Reset{map: self.rotation.current_name().to_string(), gamemode: self.gamemode});
+		self.creature_index = SpatialIndex::new(self.size);
+		if self.editor.is_none() {
+			for (pos, creature) in template.creatures {
+				self.add_creature(Creature::create_creature(creature, pos));
+			}
+		}
+		if let Some(start) = self.payload_route.first() {
+			self.add_creature(Creature::new_payload(*start));
 		}
 		self.drawing = None;
 		for player in self.players.values_mut() {
 			player.is_new = true;
+			player.kills = 0;
+			player.map_vote = None;
+			player.mode_vote = None;
+			player.cursor = self.spawnpoint;
 		}
 		self.compute_building_distances();
+		self.compute_sanctuaries();
+	}
+	
+	/** Play these maps in turn, going to the next one after every round unless players vote otherwise. This starts a new round on the first map */
+	pub fn set_maps(&mut self, maps: Vec<(String, MapType)>) -> Result<()> {
+		self.rotation = Rotation::new(maps)?;
+		self.reset();
+		Ok(())
+	}
+	
+	/** Play these maps from the next round on. The current map stays if it is still among them */
+	pub fn queue_maps(&mut self, maps: Vec<(String, MapType)>) -> Result<()> {
+		self.pending_rotation = Some(Rotation::new(maps)?);
+		Ok(())
+	}
+	
+	fn apply_pending_rotation(&mut self) {
+		if let Some(mut rotation) = self.pending_rotation.take() {
+			rotation.select(self.rotation.current_name());
+			self.rotation = rotation;
+		}
+	}
+	
+	/** Start a new round on the map with this name */
+	pub fn change_map(&mut self, name: &str) -> Result<()> {
+		self.apply_pending_rotation();
+		if !self.rotation.select(name) {
+			return Err(aerr!("'{}' is not a map. Maps: {}", name, self.rotation.names().collect::<Vec<&str>>().join(", ")));
+		}
+		self.reset();
+		Ok(())
+	}
+	
+	/** Start a new round in this gamemode, or in the gamemode of the map if the map isn't made for it */
+	pub fn change_gamemode(&mut self, gamemode: GameMode) {
+		self.chosen_gamemode = gamemode;
+		self.reset();
+	}
+	
+	/** Change the number of teams and when rounds end. This starts a new round */
+	pub fn set_round_rules(&mut self, rules: RoundRules) {
+		self.queue_round_rules(rules);
+		self.reset();
+	}
+	
+	/** Change the number of teams and when rounds end from the next round on */
+	pub fn queue_round_rules(&mut self, rules: RoundRules) {
+		self.pending_rules = Some(rules.clamped());
+	}
+	
+	fn apply_pending_rules(&mut self) {
+		if let Some(rules) = self.pending_rules.take() {
+			self.rules = rules;
+			for (index, player) in self.players.values_mut().enumerate() {
+				player.team = index % self.rules.teams;
+			}
+		}
+	}
+	
+	/** The rules of the next round */
+	pub fn round_rules(&self) -> &RoundRules {
+		self.pending_rules.as_ref().unwrap_or(&self.rules)
+	}
+	
+	/** The team with the fewest players, for new players to join */
+	fn smallest_team(&self) -> usize {
+		(0..self.rules.teams)
+			.min_by_key(|team| self.players.values().filter(|player| player.team == *team).count())
+			.unwrap_or(0)
+	}
+	
+	fn choose_team(&mut self, playerid: &PlayerId, team: usize) -> Result<()> {
+		if !self.gamemode.has_teams() {
+			return Err(aerr!("there are no teams in this game mode"));
+		}
+		if team >= self.rules.teams {
+			return Err(aerr!("there is no team {}", team));
+		}
+		let player = self.players.get_mut(playerid).ok_or(aerr!("player not found"))?;
+		if player.team != team {
+			player.team = team;
+			let body = player.body;
+			self.remove_creature(body);
+		}
+		Ok(())
+	}
+	
+	/** The players in the same team as this player, including them */
+	pub fn teammates(&self, playerid: &PlayerId) -> Result<Vec<PlayerId>> {
+		if !self.gamemode.has_teams() {
+			return Err(aerr!("there are no teams in this game mode"));
+		}
+		let team = self.players.get(playerid).ok_or(aerr!("player not found"))?.team;
+		let mut teammates: Vec<PlayerId> = self.players.iter()
+			.filter(|(_, player)| player.team == team)
+			.map(|(id, _)| id.clone())
+			.collect();
+		teammates.sort();
+		Ok(teammates)
 	}
 	
 	pub fn add_player(&mut self, playerid: &PlayerId, sprite: Sprite) -> Result<()> {
 		if self.players.contains_key(playerid){
 			return Err(aerr!("player {} already exists", playerid));
 		}
+		let team = self.smallest_team();
 		self.players.insert(
 			playerid.clone(),
 			Player{
 				plan: None,
-				sprite: sprite,
+				sprite,
 				body: 0,
-				is_new: true
+				is_new: true,
+				is_bot: false,
+				team,
+				kills: 0,
+				map_vote: None,
+				mode_vote: None,
+				cursor: self.spawnpoint
 			}
 		);
+		self.balance_bots();
 		Ok(())
 	}
 	
 	pub fn remove_player(&mut self, playerid: &PlayerId) -> Result<()> {
 		let player = self.players.remove(playerid).ok_or(aerr!("player {} not found", playerid))?;
-		self.creatures.remove(&player.body);
+		self.remove_creature(player.body);
+		self.balance_bots();
 		Ok(())
 	}
 	
+	/** How much time one update stands for, which round time limits are counted in */
+	pub fn set_step_duration(&mut self, step_duration: std::time::Duration) {
+		self.step_duration = step_duration;
+	}
+	
+	/** Add or remove bots so that there are at least this many players when possible */
+	pub fn set_min_players(&mut self, min_players: usize) {
+		self.min_players = min_players;
+		self.balance_bots();
+	}
+	
+	fn balance_bots(&mut self) {
+		while self.players.len() < self.min_players {
+			let playerid = (1..)
+				.map(|n| PlayerId(format!("bot-{}", n)))
+				.find(|id| !self.players.contains_key(id))
+				.unwrap();
+			let team = self.smallest_team();
+			self.players.insert(
+				playerid,
+				Player{
+					plan: None,
+					sprite: Sprite::Player("lc", 'b'),
+					body: 0,
+					is_new: true,
+					is_bot: true,
+					team,
+					kills: 0,
+					map_vote: None,
+					mode_vote: None,
+					cursor: self.spawnpoint
+				}
+			);
+		}
+		while self.players.len() > self.min_players {
+			let Some(botid) = self.players.iter().find(|(_, p)| p.is_bot).map(|(id, _)| id.clone()) else {
+				break;
+			};
+			let bot = self.players.remove(&botid).unwrap();
+			self.remove_creature(bot.body);
+		}
+	}
+	
+	fn add_creature(&mut self, creature: Creature) -> usize {
+		let pos = creature.pos;
+		let id = self.creatures.insert(creature);
+		self.creature_index.insert(id, pos);
+		id
+	}
+	
+	fn remove_creature(&mut self, id: usize) -> Option<Creature> {
+		let creature = self.creatures.remove(&id)?;
+		self.creature_index.remove(id, creature.pos);
+		if let Some(Item::Flag(team)) = creature.carried {
+			self.drop_flag(team, creature.pos);
+		}
+		Some(creature)
+	}
+	
+	/** Leave a flag where its carrier fell, or next to it when something lies there already */
+	fn drop_flag(&mut self, team: usize, pos: Pos) {
+		put_item_near(&mut self.items, &self.ground, pos, Item::Flag(team));
+		self.announcements.push(format!("The {} flag was dropped", team_name(team)));
+	}
+	
+	/** Set what a player will do in the next update */
 	pub fn control_player(&mut self, playerid: PlayerId, control: Control) -> Result<()>{
+		if let Control::Team(team) = control {
+			return self.choose_team(&playerid, team);
+		}
+		if let Control::Vote(option) = control {
+			return self.vote(&playerid, &option);
+		}
+		if control.is_server_command() {
+			return Err(aerr!("{:?} is handled by the server", control));
+		}
+		if self.editor.is_some() {
+			return self.edit(&playerid, control);
+		}
+		if control.is_edit() {
+			return Err(aerr!("the map can only be changed in the editor gamemode"));
+		}
 		let player = self.players.get_mut(&playerid).ok_or(aerr!("player not found"))?;
 		player.plan = Some(control);
 		Ok(())
 	}
 	
+	/** Move the cursor of a player in the editor, or change the map under it */
+	fn edit(&mut self, playerid: &PlayerId, control: Control) -> Result<()> {
+		let player = self.players.get_mut(playerid).ok_or(aerr!("player not found"))?;
+		if let Control::Move(direction) = control {
+			let next = player.cursor + direction;
+			if self.ground.get(next).is_some() {
+				player.cursor = next;
+			}
+			return Ok(());
+		}
+		let Some(editor) = self.editor.as_mut() else {
+			return Err(aerr!("not in the editor"));
+		};
+		if !control.is_edit() {
+			// there is nothing to shoot in the editor
+			return Ok(());
+		}
+		let tile = editor.apply(player.cursor, &control)?;
+		self.ground.set(player.cursor, tile);
+		Ok(())
+	}
+	
+	/** The map as it is in the editor now */
+	pub fn edited_map(&self) -> Option<&MapTemplate> {
+		self.editor.as_ref().map(Editor::template)
+	}
+	
+	fn vote(&mut self, playerid: &PlayerId, option: &str) -> Result<()> {
+		let vote = self.rotation.parse_vote(option)?;
+		let player = self.players.get_mut(playerid).ok_or(aerr!("player not found"))?;
+		match vote {
+			Vote::Map(index) => player.map_vote = Some(index),
+			Vote::GameMode(gamemode) => player.mode_vote = Some(gamemode)
+		}
+		self.announcements.push(format!("{} votes for {}", playerid, option));
+		Ok(())
+	}
+	
+	/** Pick the map and gamemode of the next round from the votes and start it */
+	fn next_round(&mut self) {
+		let map_vote = most_voted(self.players.values().filter_map(|player| player.map_vote));
+		self.rotation.next(map_vote);
+		if let Some(gamemode) = most_voted(self.players.values().filter_map(|player| player.mode_vote)) {
+			self.chosen_gamemode = gamemode;
+		}
+		self.reset();
+	}
+	
 	fn compute_player_distances(&mut self) {
 		self.player_distances = self.distance_map(
 			&self.creatures.values()
@@ -147,16 +543,192 @@ impl World {
 				.collect::<Vec<Pos>>()
 		);
 	}
-	fn compute_building_distances(&mut self) {
-		self.building_distances = self.distance_map(
+	fn compute_monster_distances(&mut self) {
+		// bots can leave the sanctuary through the gates
+		self.monster_distances = self.distance_map_with(
 			&self.creatures.values()
-				.filter(|c| c.is_building && c.alignment != Alignment::Monsters)
+				.filter(|c| !c.is_building && c.alignment == Alignment::Monsters)
 				.map(|c| c.pos)
-				.collect::<Vec<Pos>>()
+				.collect::<Vec<Pos>>(),
+			|tile| !tile.blocking() || tile == &Tile::Gate
 		);
 	}
+	/** Find which team owns which sanctuary and how far every place is from a sanctuary its players can use */
+	fn compute_sanctuaries(&mut self) {
+		let mut sanctuary = Vec::new();
+		for y in 0..self.size.y {
+			for x in 0..self.size.x {
+				if self.ground.get(Pos::new(x, y)) == Some(&Tile::Sanctuary) {
+					sanctuary.push(Pos::new(x, y));
+				}
+			}
+		}
+		// in team games the sanctuary around a team's spawnpoint is theirs, unless another team spawns there too
+		let mut owners: HashMap<Pos, Option<usize>> = HashMap::new();
+		if self.gamemode.has_teams() {
+			for (index, team) in self.teams.iter().enumerate() {
+				for pos in self.sanctuary_region(team.spawnpoint) {
+					owners.entry(pos)
+						.and_modify(|owner| if *owner != Some(index) {*owner = None})
+						.or_insert(Some(index));
+				}
+			}
+		}
+		self.sanctuary_owners = owners.into_iter()
+			.filter_map(|(pos, owner)| Some((pos, owner?)))
+			.collect();
+		let nmaps = if self.gamemode.has_teams() {self.teams.len()} else {1};
+		// gates only let creatures out, so from outside the closest a creature can get is the ground in front of a gate
+		self.sanctuary_distances = (0..nmaps)
+			.map(|team| {
+				let usable: Vec<Pos> = sanctuary.iter()
+					.filter(|pos| self.sanctuary_owners.get(pos).is_none_or(|owner| *owner == team))
+					.cloned()
+					.collect();
+				let mut targets = usable.clone();
+				for pos in usable {
+					for gate in Direction::DIRECTIONS.iter().map(|dir| pos + *dir) {
+						if self.ground.get(gate) == Some(&Tile::Gate) {
+							targets.extend(
+								Direction::DIRECTIONS.iter()
+									.map(|dir| gate + *dir)
+									.filter(|front| self.ground.get(*front).is_some_and(|tile| !tile.blocking() && tile != &Tile::Sanctuary))
+							);
+						}
+					}
+				}
+				self.distance_map(&targets)
+			})
+			.collect();
+		self.spawn_distances = if self.gamemode.has_teams() {
+			self.teams.iter()
+				.map(|team| self.distance_map_with(&[team.spawnpoint], |tile| !tile.blocking() || tile == &Tile::Gate))
+				.collect()
+		} else {
+			Vec::new()
+		};
+		self.base_distances = if self.gamemode == GameMode::CaptureTheFlag {
+			self.teams.iter()
+				.map(|team| self.distance_map_with(&team.base.into_iter().collect::<Vec<Pos>>(), |tile| !tile.blocking() || tile == &Tile::Gate))
+				.collect()
+		} else {
+			Vec::new()
+		};
+	}
+	
+	fn compute_flag_distances(&mut self) {
+		self.flag_distances = (0..self.teams.len())
+			.map(|team| self.distance_map_with(&self.flag_position(team).into_iter().collect::<Vec<Pos>>(), |tile| !tile.blocking() || tile == &Tile::Gate))
+			.collect();
+	}
+	
+	/** Where the flag of a team is now, either on the ground or with the creature carrying it */
+	fn flag_position(&self, team: usize) -> Option<Pos> {
+		self.items.iter()
+			.find(|(_, item)| **item == Item::Flag(team))
+			.map(|(pos, _)| *pos)
+			.or_else(||
+				self.creatures.values()
+					.find(|creature| creature.carried == Some(Item::Flag(team)))
+					.map(|creature| creature.pos)
+			)
+	}
+	
+	/** All sanctuary tiles connected to pos */
+	fn sanctuary_region(&self, pos: Pos) -> HashSet<Pos> {
+		let mut region = HashSet::new();
+		let mut frontier = vec![pos];
+		while let Some(pos) = frontier.pop() {
+			if self.ground.get(pos) == Some(&Tile::Sanctuary) && region.insert(pos) {
+				frontier.extend(Direction::DIRECTIONS.iter().map(|dir| pos + *dir));
+			}
+		}
+		region
+	}
+	
+	fn sanctuary_distances_for(&self, creature: &Creature) -> &Grid<Option<usize>> {
+		match creature.alignment {
+			Alignment::Team(team) if team < self.sanctuary_distances.len() => &self.sanctuary_distances[team],
+			_ => &self.sanctuary_distances[0]
+		}
+	}
+	fn compute_building_distances(&mut self) {
+		let mut targets: Vec<Pos> = self.creatures.values()
+			.filter(|c| c.is_building && c.alignment != Alignment::Monsters)
+			.map(|c| c.pos)
+			.collect();
+		// without pillars to destroy, monsters go for the zones they don't hold yet
+		if self.gamemode == GameMode::ControlPoints {
+			targets.extend(
+				self.zones.iter()
+					.filter(|zone| zone.owner != Some(Alignment::Monsters))
+					.flat_map(|zone| zone.positions())
+			);
+		}
+		self.building_distances = self.distance_map(&targets);
+	}
+	
+	fn compute_zone_distances(&mut self) {
+		if self.gamemode != GameMode::KingOfTheHill {
+			return;
+		}
+		self.zone_distances = (0..self.teams.len())
+			.map(|team| {
+				let mut targets: Vec<Pos> = self.zones.iter()
+					.filter(|zone| zone.owner != Some(Alignment::Team(team)))
+					.flat_map(|zone| zone.positions())
+					.collect();
+				if targets.is_empty() {
+					// everything is held already, so defend it
+					targets = self.zones.iter().flat_map(|zone| zone.positions()).collect();
+				}
+				self.distance_map_with(&targets, |tile| !tile.blocking() || tile == &Tile::Gate)
+			})
+			.collect();
+	}
+	
+	/** Let everyone standing in a zone capture it, and give its owner a point */
+	fn update_zones(&mut self) {
+		let creature_index = &self.creature_index;
+		let creatures = &self.creatures;
+		let mut changed = false;
+		for zone in self.zones.iter_mut() {
+			let present: Vec<Alignment> = creature_index.in_radius(zone.center, zone.radius)
+				.into_iter()
+				.filter_map(|(id, _)| creatures.get(&id))
+				.filter(|creature| !creature.is_building)
+				.map(|creature| creature.alignment.clone())
+				.collect();
+			if zone.update(&present) {
+				changed = true;
+				if let Some(owner) = &zone.owner {
+					self.announcements.push(format!("{} captured a zone", side_name(owner)));
+				}
+			}
+			match &zone.owner {
+				Some(Alignment::Team(team)) => {
+					if let Some(team) = self.teams.get_mut(*team) {
+						team.score += 1;
+					}
+				}
+				Some(Alignment::Players) => {
+					self.coop_score += 1;
+				}
+				Some(Alignment::Player(_)) | Some(Alignment::Monsters) | None => {}
+			}
+		}
+		if changed {
+			self.compute_building_distances();
+			self.compute_zone_distances();
+		}
+	}
 	
 	fn distance_map(&self, targets: &[Pos]) -> Grid<Option<usize>>{
+		self.distance_map_with(targets, |tile| !tile.blocking())
+	}
+	
+	fn distance_map_with<F>(&self, targets: &[Pos], passable: F) -> Grid<Option<usize>>
+			where F: Fn(&Tile) -> bool {
 		let mut frontier: VecDeque<(Pos, usize)> = targets.iter().map(|pos| (*pos, 0)).collect();
 		let mut known: Grid<Option<usize>> = Grid::new(self.size, None);
 		while let Some((pos, cost)) = frontier.pop_front() {
@@ -166,7 +738,7 @@ impl World {
 			known.set_unchecked(pos, Some(cost));
 			for dir in &Direction::DIRECTIONS {
 				if let Some(tile) = self.ground.get(pos + *dir) {
-					if !tile.blocking(){
+					if passable(tile){
 						frontier.push_back((pos + *dir, cost + 1));
 					}
 				}
@@ -178,52 +750,151 @@ impl World {
 	fn monster_plan<F>(&self, creature: &Creature, distance_map: &Grid<Option<usize>>, is_target: F, deviation: &Percentage) -> Option<Control>
 			where F: Fn(&Creature) -> bool {
 		// find nearest attackable target
-		let mut target = None;
-		for player in self.creatures.values() {
-			if is_target(player) {
-				if let Some(target_pos) = target {
-					if creature.pos.distance_to(player.pos) < creature.pos.distance_to(target_pos) {
-						target = Some(player.pos);
-					}
-				} else {
-					target = Some(player.pos);
-				}
+		let target = self.creature_index.nearest(
+			creature.pos,
+			|id| self.creatures.get(&id).is_some_and(&is_target)
+		);
+		if let Some((_, target_pos)) = target {
+			if let Some(shoot) = self.shoot_plan(creature, target_pos) {
+				return Some(shoot);
 			}
 		}
-		if let Some(target_pos) = target {
-			let range = creature.range();
-			let distance = creature.pos.distance_to(target_pos);
-			if range <= Distance(5) && distance <= range
-					|| distance.0 * 11 <= range.0 * 10 {
-				return Some(Control::ShootPrecise(target_pos - creature.pos))
-			}
+		self.move_plan(creature, distance_map, deviation)
+	}
+	
+	fn shoot_plan(&self, creature: &Creature, target_pos: Pos) -> Option<Control> {
+		let range = creature.range();
+		let distance = creature.pos.distance_to(target_pos);
+		if range <= Distance(5) && distance <= range
+				|| distance.0 * 11 <= range.0 * 10 {
+			Some(Control::ShootPrecise(target_pos - creature.pos))
+		} else {
+			None
 		}
+	}
+	
+	fn move_plan(&self, creature: &Creature, distance_map: &Grid<Option<usize>>, deviation: &Percentage) -> Option<Control> {
+		// bots leave the sanctuary through the gates like players, monsters keep to open ground
+		let can_step = |pos: Pos| match creature.mind {
+			Mind::Bot(_) => can_walk(&self.ground, creature, pos),
+			_ => self.ground.get(pos).is_some_and(|tile| !tile.blocking())
+		};
 		let mut dirs: Vec<Direction> = Direction::DIRECTIONS.iter()
-			.filter_map(|dir| {
-				let newpos = creature.pos + *dir;
-				if let Some(tile) = self.ground.get(newpos) {
-					if !tile.blocking() {
-						return Some(*dir);
-					}
-				}
-				None
-			})
+			.filter(|dir| can_step(creature.pos + **dir))
+			.cloned()
 			.collect();
-		let mut rng = thread_rng();
+		let mut rng = rng();
 		dirs.shuffle(&mut rng);
 		if rng.gen_range(0..100) >= deviation.0 {
-			dirs.sort_by_key(|dir| distance_map.get(creature.pos + *dir).unwrap_or(&None).unwrap_or(std::usize::MAX));
+			dirs.sort_by_key(|dir| distance_map.get(creature.pos + *dir).unwrap_or(&None).unwrap_or(usize::MAX));
 		}
 		Some(Control::Move(*dirs.first()?))
 	}
 	
+	/** Whether a straight line between the positions is free of tiles that stop bullets */
+	fn has_line_of_fire(&self, from: Pos, to: Pos) -> bool {
+		let d = to - from;
+		let steps = d.x.abs().max(d.y.abs());
+		(1..steps).all(|i| {
+			let pos = from + Pos::new(
+				(d.x * i * 2 + steps * d.x.signum()) / (steps * 2),
+				(d.y * i * 2 + steps * d.y.signum()) / (steps * 2)
+			);
+			self.ground.get(pos).is_some_and(|tile| !tile.bullet_blocking())
+		})
+	}
+	
+	fn bot_plan(&self, creature: &Creature) -> Option<Control> {
+		let is_enemy = |other: &Creature|
+			other.alignment != creature.alignment
+			&& !other.is_building
+			&& self.ground.get(other.pos) != Some(&Tile::Sanctuary);
+		let target = self.creature_index.nearest(
+			creature.pos,
+			|id| self.creatures.get(&id).is_some_and(is_enemy)
+		).map(|(_, pos)| pos);
+		// pick the weapon with the shortest range that still reaches the target
+		if let Some(target_pos) = target {
+			let distance = creature.pos.distance_to(target_pos);
+			let wanted = creature.weapons.iter()
+				.enumerate()
+				.filter(|(_, weapon)| weapon.get_range() >= distance)
+				.min_by_key(|(_, weapon)| weapon.get_range())
+				.or_else(|| creature.weapons.iter().enumerate().max_by_key(|(_, weapon)| weapon.get_range()))
+				.map(|(index, _)| index);
+			if let Some(index) = wanted {
+				if index != creature.selected_weapon {
+					return Some(Control::NextWeapon);
+				}
+			}
+		}
+		if let (Some(Item::Flag(_)), Alignment::Team(team)) = (creature.carried, &creature.alignment) {
+			return self.move_plan(creature, self.base_distances.get(*team)?, &Percentage(0));
+		}
+		let in_sanctuary = self.ground.get(creature.pos) == Some(&Tile::Sanctuary)
+			&& can_use_sanctuary(&self.sanctuary_owners, creature);
+		let low_health = creature.health.0 * 100 < creature.max_health.0 * BOT_RETREAT_HEALTH.0 && !in_sanctuary;
+		let retreat_distance = self.sanctuary_distances_for(creature).get(creature.pos).copied().flatten();
+		if low_health && retreat_distance.is_some_and(|distance| distance > 0) {
+			return self.move_plan(creature, self.sanctuary_distances_for(creature), &Percentage(0));
+		}
+		if let Some(target_pos) = target {
+			// nothing can be shot from inside a gate
+			let can_shoot = self.ground.get(creature.pos).is_some_and(|tile| !tile.blocking());
+			if can_shoot && self.has_line_of_fire(creature.pos, target_pos) {
+				if let Some(shoot) = self.shoot_plan(creature, target_pos) {
+					return Some(shoot);
+				}
+			}
+		}
+		if in_sanctuary && !creature.has_full_health() || low_health && retreat_distance == Some(0) {
+			// stay where it's safest until healed
+			return None;
+		}
+		if let (GameMode::CaptureTheFlag, Alignment::Team(team)) = (self.gamemode, &creature.alignment) {
+			// get the own flag back when it's gone, otherwise go for the flag of the next team
+			let home = self.teams[*team].flag;
+			let goal = if home.is_none() || home.and_then(|pos| self.items.get(&pos)) == Some(&Item::Flag(*team)) {
+				(team + 1) % self.teams.len()
+			} else {
+				*team
+			};
+			return self.move_plan(creature, self.flag_distances.get(goal)?, &BOT_DEVIATION);
+		}
+		if let (GameMode::KingOfTheHill, Alignment::Team(team)) = (self.gamemode, &creature.alignment) {
+			return self.move_plan(creature, self.zone_distances.get(*team)?, &BOT_DEVIATION);
+		}
+		if self.gamemode == GameMode::Escort && !self.payload_route.is_empty() {
+			// the payload only moves with someone next to it
+			return self.move_plan(creature, &self.building_distances, &BOT_DEVIATION);
+		}
+		if let (None, Alignment::Team(team)) = (target, &creature.alignment) {
+			// go to where the next team spawns to find someone to fight
+			let enemy = (team + 1) % self.spawn_distances.len().max(1);
+			return self.move_plan(creature, self.spawn_distances.get(enemy)?, &BOT_DEVIATION);
+		}
+		if self.gamemode.is_pvp() {
+			let target_pos = target?;
+			let dir = *creature.pos.directions_to(target_pos).iter()
+				.find(|dir| can_walk(&self.ground, creature, creature.pos + **dir))?;
+			Some(Control::Move(dir))
+		} else {
+			self.move_plan(creature, &self.monster_distances, &BOT_DEVIATION)
+		}
+	}
+	
 	fn creature_plan(&self, creature: &Creature) -> Option<Control> {
 		match &creature.mind {
 			Mind::Player(playerid) => {
-				if let Some(player) = self.players.get(&playerid) {
+				if let Some(player) = self.players.get(playerid) {
 					player.plan.clone()
 				} else {Some(Control::Suicide)}
 			}
+			Mind::Bot(playerid) => {
+				if self.players.contains_key(playerid) {
+					self.bot_plan(creature)
+				} else {Some(Control::Suicide)}
+			}
 			Mind::BloodThirst(deviation) => {
 				self.monster_plan(
 					creature,
@@ -245,15 +916,39 @@ impl World {
 					&Percentage(0)
 				)
 			}
-			Mind::Pillar => None
+			Mind::Pillar => None,
+			Mind::Payload => self.payload_plan(creature)
 		}
 	}
 	
+	/** The payload rolls on along its route while a player is close */
+	fn payload_plan(&self, creature: &Creature) -> Option<Control> {
+		let next = *self.payload_route.get(self.payload_progress + 1)?;
+		let (_, escort) = self.creature_index.nearest(
+			creature.pos,
+			|id| self.creatures.get(&id).is_some_and(Creature::is_player)
+		)?;
+		if creature.pos.distance_to(escort) > ESCORT_DISTANCE {
+			return None;
+		}
+		Some(Control::Move(*creature.pos.directions_to(next).first()?))
+	}
+	
+	fn payload_arrived(&self) -> bool {
+		!self.payload_route.is_empty() && self.payload_progress + 1 >= self.payload_route.len()
+	}
+	
 	fn update_creatures(&mut self) {
-		let mut creature_map: HashMap<Pos, usize> = self.creatures.iter()
-			.map(|(creatureid, creature)| (creature.pos, *creatureid))
-			.collect();
 		self.compute_player_distances();
+		if self.players.values().any(|player| player.is_bot) {
+			if !self.gamemode.is_pvp() {
+				self.compute_monster_distances();
+			}
+			if self.gamemode == GameMode::CaptureTheFlag {
+				self.compute_flag_distances();
+			}
+		}
+		let mut payload_moved = false;
 		let plans: HashMap<usize, Control> = self.creatures.iter()
 			.filter(|(_k, c)| c.cooldown.0 <= 0)
 			.filter_map(|(k, c)|
@@ -263,7 +958,7 @@ impl World {
 			if creature.is_dead() {
 				continue;
 			}
-			if self.ground.get(creature.pos) == Some(&Tile::Sanctuary) {
+			if self.ground.get(creature.pos) == Some(&Tile::Sanctuary) && can_use_sanctuary(&self.sanctuary_owners, creature) {
 				creature.heal(Health(2));
 			} else if self.round_state.is_paused() {
 				creature.heal(Health(if creature.is_building {20} else {2}));
@@ -277,26 +972,23 @@ impl World {
 					creature.cooldown = creature.walk_cooldown;
 					creature.dir = *direction;
 					let newpos = creature.pos + *direction;
-					if let Some(tile) = self.ground.get(newpos) {
-						if (
-								!tile.blocking()
-									|| tile == &Tile::Gate
-										&& self.ground.get(creature.pos) == Some(&Tile::Sanctuary)
-										&& creature.has_full_health())
-								&& !creature_map.contains_key(&newpos) {
-							if creature_map.get(&creature.pos) == Some(id){
-								creature_map.remove(&creature.pos);
-							}
-							creature_map.insert(newpos, *id);
-							creature.pos = newpos;
-							if let Mind::Player(_) = creature.mind {
-								match self.items.get(&creature.pos) {
-									Some(Item::Health) => {
-										creature.heal(Health(100));
-										self.items.remove(&creature.pos);
-									}
-									None => {}
+					if can_walk(&self.ground, creature, newpos) && !self.creature_index.is_occupied(newpos) {
+						self.creature_index.move_to(*id, creature.pos, newpos);
+						creature.pos = newpos;
+						if creature.mind == Mind::Payload {
+							self.payload_progress += 1;
+							payload_moved = true;
+						}
+						if creature.is_player() {
+							match self.items.get(&creature.pos) {
+								Some(Item::Health) => {
+									creature.heal(Health(100));
+									self.items.remove(&creature.pos);
 								}
+								Some(Item::Flag(_)) | None => {}
+							}
+							if let Some(announcement) = touch_flags(creature, &mut self.items, &self.ground, &mut self.teams) {
+								self.announcements.push(announcement);
 							}
 						}
 					}
@@ -334,6 +1026,9 @@ impl World {
 				}
 				Some(Control::Suicide) => {
 					creature.kill();
+					if let Some(player) = creature.player_id() {
+						self.events.push(GameEvent::Death{player: player.clone(), cause: "suicide".to_string()});
+					}
 				}
 				Some(Control::NextWeapon) => {
 					creature.select_next_weapon();
@@ -341,15 +1036,15 @@ impl World {
 				Some(Control::PreviousWeapon) => {
 					creature.select_previous_weapon();
 				}
-				None => {}
+				Some(_) | None => {}
 			}
 		}
+		if payload_moved {
+			self.compute_building_distances();
+		}
 	}
 	
 	fn update_bullets(&mut self) {
-		let creature_map: HashMap<Pos, usize> = self.creatures.iter()
-			.map(|(creatureid, creature)| (creature.pos, *creatureid))
-			.collect();
 		self.bullets = self.bullets.clone().into_iter().filter_map(|mut bullet| {
 			for i in 0..(bullet.ammo.speed + 1) {
 				/* bullet movement */
@@ -362,10 +1057,35 @@ impl World {
 					self.particles.insert(bullet.pos, bullet.sprite());
 				}
 				/* hit creature */
-				if let Some(creatureid) = creature_map.get(&bullet.pos){
+				if let Some(creatureid) = self.creature_index.at(bullet.pos).first(){
 					if let Some(creature) = self.creatures.get_mut(creatureid){
 						if creature.alignment != bullet.alignment {
+							let was_alive = !creature.is_dead();
 							creature.damage(bullet.ammo.damage);
+							if was_alive && creature.is_dead() && creature.is_player() {
+								if let Some(player) = creature.player_id() {
+									let cause = match &bullet.alignment {
+										Alignment::Player(killer) => format!("shot by {}", killer),
+										Alignment::Team(team) => format!("shot by the {} team", team_name(*team)),
+										Alignment::Players => "shot by the players".to_string(),
+										Alignment::Monsters => "killed by monsters".to_string()
+									};
+									self.events.push(GameEvent::Death{player: player.clone(), cause});
+								}
+								match &bullet.alignment {
+									Alignment::Player(killer) => {
+										if let Some(player) = self.players.get_mut(killer) {
+											player.kills += 1;
+										}
+									}
+									Alignment::Team(team) if self.gamemode == GameMode::Teams => {
+										if let Some(team) = self.teams.get_mut(*team) {
+											team.score += 1;
+										}
+									}
+									Alignment::Team(_) | Alignment::Players | Alignment::Monsters => {}
+								}
+							}
 							return None;
 						}
 					}
@@ -386,12 +1106,32 @@ impl World {
 		// spawn players
 		for (playerid, player) in self.players.iter_mut() {
 			if !self.creatures.contains_key(&player.body) {
-				let body = self.creatures.insert(Creature::new_player(
-					playerid.clone(),
-					player.sprite,
-					self.spawnpoint,
-					self.gamemode == GameMode::PvP
-				));
+				let (pos, sprite, alignment) = match self.gamemode {
+					GameMode::Teams | GameMode::CaptureTheFlag | GameMode::KingOfTheHill => (
+						spawn_position(&self.spawnzones, &self.ground, Some(player.team))
+							.unwrap_or(self.teams[player.team].spawnpoint),
+						team_sprite(player.team, player.sprite),
+						Alignment::Team(player.team)
+					),
+					GameMode::PvP => (
+						spawn_position(&self.spawnzones, &self.ground, None).unwrap_or(self.spawnpoint),
+						player.sprite,
+						Alignment::Player(playerid.clone())
+					),
+					GameMode::Survival | GameMode::PillarDefence | GameMode::ControlPoints | GameMode::Escort | GameMode::Editor => (
+						spawn_position(&self.spawnzones, &self.ground, None).unwrap_or(self.spawnpoint),
+						player.sprite,
+						Alignment::Players
+					)
+				};
+				let body = self.creatures.insert(
+					if player.is_bot {
+						Creature::new_bot(playerid.clone(), sprite, pos, alignment)
+					} else {
+						Creature::new_player(playerid.clone(), sprite, pos, alignment)
+					}
+				);
+				self.creature_index.insert(body, pos);
 				player.body = body
 			}
 			player.plan = None;
@@ -399,8 +1139,9 @@ impl World {
 		
 		// spawn monsters
 		let nmonsters = self.creatures.values().filter(|c| c.alignment == Alignment::Monsters).count();
-		if self.gamemode != GameMode::PvP && nmonsters == 0 && self.to_spawn.is_empty() {
+		if !self.gamemode.is_pvp() && nmonsters == 0 && self.to_spawn.is_empty() {
 			self.wave += 1;
+			self.events.push(GameEvent::WaveStart{wave: self.wave});
 			self.round_state = RoundState::Paused(Duration(25));
 			self.to_spawn =
 				wave_composition(self.wave)
@@ -416,17 +1157,25 @@ impl World {
 				RoundState::Paused(pause - Duration(1))
 			};
 		} else if self.time.0 % 5 == 0 && !self.to_spawn.is_empty() {
-			self.creatures.insert(Creature::create_creature(
-				self.to_spawn.remove(0),
-				self.monsterspawn[thread_rng().gen_range(0..self.monsterspawn.len())],
-			));
+			let wave = self.wave;
+			let active: Vec<MonsterSpawn> = self.monsterspawn.iter()
+				.filter(|spawn| spawn.is_active(wave))
+				.cloned()
+				.collect();
+			// waves that a map has no spawns for, or only spawns with weight 0, still get their monsters
+			let spawn = active.choose_weighted(&mut rng(), |spawn| spawn.weight)
+				.or_else(|_| self.monsterspawn.choose_weighted(&mut rng(), |spawn| spawn.weight))
+				.map(|spawn| spawn.pos)
+				.unwrap_or_else(|_| self.monsterspawn[rng().gen_range(0..self.monsterspawn.len())].pos);
+			let creature = Creature::create_creature(self.to_spawn.remove(0), spawn);
+			self.add_creature(creature);
 		}
 		
-		let nplayers = std::cmp::max(self.players.len(), 1);
+		let nplayers = std::cmp::max(self.nplayers(), 1);
 		// spawn items
 		for creature in dead_creatures {
-			if creature.alignment != Alignment::Players && self.items.len() < nplayers + 1  && thread_rng().gen_range(0..10) == 0{
-				self.items.insert(creature.pos, Item::Health);
+			if creature.alignment != Alignment::Players && self.items.len() < nplayers + 1  && rng().gen_range(0..10) == 0{
+				self.items.entry(creature.pos).or_insert(Item::Health);
 			}
 		}
 	}
@@ -443,39 +1192,66 @@ impl World {
 		}
 	}
 	
+	/** Advance the world by one step */
 	pub fn update(&mut self) {
+		if self.events.len() > MAX_EVENTS {
+			self.events.drain(..self.events.len() - MAX_EVENTS);
+		}
+		if self.editor.is_some() {
+			self.time.increment();
+			return;
+		}
 		match self.round_state {
 			RoundState::Running | RoundState::Paused(_) => {
 				self.particles.clear();
+				let start = Instant::now();
 				self.update_creatures();
+				let creatures_done = Instant::now();
 				self.update_bullets();
+				let bullets_done = Instant::now();
+				self.phase_times.update_creatures += creatures_done - start;
+				self.phase_times.update_bullets += bullets_done - creatures_done;
 				let mut dead_creatures = Vec::new();
 				let creatureids: Vec<usize> = self.creatures.keys().cloned().collect();
 				for creatureid in creatureids {
 					if self.creatures.get(&creatureid).unwrap().is_dead() {
-						dead_creatures.push(self.creatures.remove(&creatureid).unwrap());
+						dead_creatures.push(self.remove_creature(creatureid).unwrap());
+					}
+				}
+				for creature in dead_creatures.iter() {
+					if creature.alignment == Alignment::Monsters {
+						self.monsters_killed += 1;
+					} else if creature.is_player() {
+						self.deaths += 1;
 					}
 				}
 				if dead_creatures.iter().any(|c|c.is_building && c.alignment == Alignment::Players){
 					self.compute_building_distances();
 				}
+				let spawn_start = Instant::now();
 				self.spawn(dead_creatures);
+				self.phase_times.spawn += spawn_start.elapsed();
+				self.update_zones();
 				
 				if self.is_game_over() {
-					self.round_state = RoundState::GameOver(Duration(50));
+					self.round_won = self.is_won();
+					self.round_state = RoundState::GameOver(GAME_OVER_DURATION);
+					self.events.push(GameEvent::GameOver{won: self.round_won, scores: self.scores()});
 				}
 				self.time.increment();
+				self.round_time += self.step_duration;
 			}
 			
 			
 			RoundState::GameOver(time_left) => {
-				let mut rng = thread_rng();
-				let gopos = Pos::new(rng.gen_range(0..(self.size.x - 10)), rng.gen_range(0..self.size.y));
-				for (i, c) in "GAME_OVER!".chars().enumerate() {
+				let mut rng = rng();
+				// maps can be narrower than the text, which then gets cut off
+				let gopos = Pos::new(rng.gen_range(0..=(self.size.x - GAME_OVER_TEXT.len() as i64).max(0)), rng.gen_range(0..self.size.y));
+				for (i, c) in GAME_OVER_TEXT.chars().enumerate().take(self.size.x as usize) {
 					self.particles.insert(Pos::new(gopos.x + (i as i64), gopos.y), Sprite::letter_sprite(c).unwrap());
 				}
 				if time_left.0 <= 0 {
-					self.reset();
+					self.next_round();
 				} else {
 					self.round_state = RoundState::GameOver(time_left - Duration(1));
 				}
@@ -486,13 +1262,85 @@ impl World {
 	fn is_game_over(&self) -> bool {
 		match self.gamemode {
 			GameMode::PillarDefence =>
-				!self.creatures.values()
+				self.has_survived() || !self.creatures.values()
 					.any(|c| c.mind == Mind::Pillar && c.alignment == Alignment::Players),
 			GameMode::Survival =>
-				self.wave > 1 && !self.creatures.values()
+				self.has_survived() || self.wave > 1 && !self.creatures.values()
 					.any(|c| c.is_player() && self.ground.get(c.pos) != Some(&Tile::Sanctuary)),
-			GameMode::PvP => false
+			GameMode::ControlPoints =>
+				self.has_survived()
+				|| !self.zones.is_empty() && self.zones.iter().all(|zone| zone.owner == Some(Alignment::Monsters)),
+			GameMode::Escort =>
+				!self.payload_route.is_empty()
+				&& (self.payload_arrived() || !self.creatures.values().any(|c| c.mind == Mind::Payload)),
+			GameMode::Editor => false,
+			GameMode::PvP | GameMode::Teams | GameMode::CaptureTheFlag | GameMode::KingOfTheHill =>
+				self.rules.time_limit.is_some_and(|limit| self.round_time >= limit)
+				|| self.score_limit().is_some_and(|limit| self.scores().iter().any(|(_, score)| *score >= limit))
+		}
+	}
+	
+	fn score_limit(&self) -> Option<u64> {
+		self.rules.score_limit
+			.or(self.gamemode.default_score_limit())
+			.filter(|limit| *limit > 0)
+	}
+	
+	fn has_survived(&self) -> bool {
+		self.survive_waves.is_some_and(|waves| self.wave > waves)
+	}
+	
+	/** Whether a round that is over ended in victory. Player versus player rounds always have a winner or a draw */
+	fn is_won(&self) -> bool {
+		match self.gamemode {
+			GameMode::PillarDefence | GameMode::Survival | GameMode::ControlPoints => self.has_survived(),
+			GameMode::Escort => self.payload_arrived(),
+			GameMode::PvP | GameMode::Teams | GameMode::CaptureTheFlag | GameMode::KingOfTheHill | GameMode::Editor => true
+		}
+	}
+	
+	/** What everyone is told when the round is over */
+	fn game_over_messages(&self) -> Vec<String> {
+		let mut messages = Vec::new();
+		if self.gamemode.is_pvp() {
+			let scores = self.scores();
+			messages.push(match scores.first() {
+				Some((name, score)) if scores.get(1).is_none_or(|(_, second)| second < score) =>
+					format!("**** {} wins with a score of {} ****", name, score),
+				_ => "**** The round ended in a draw ****".to_string()
+			});
+		} else {
+			messages.push(match self.gamemode {
+				GameMode::Escort if self.round_won => "**** The payload has arrived ****".to_string(),
+				GameMode::Escort => "**** The payload was destroyed ****".to_string(),
+				_ if self.round_won => format!("**** Victory! All {} waves survived ****", self.wave - 1),
+				_ => format!("**** Defeated in wave {} ****", self.wave)
+			});
+			messages.push(format!("{} monsters killed, {} deaths", self.monsters_killed, self.deaths));
 		}
+		messages.push(format!(
+			"Vote for the next round with /vote and a map or gamemode. Maps: {}. Gamemodes: {}",
+			self.rotation.names().collect::<Vec<&str>>().join(", "),
+			GameMode::ALL.iter().map(|gamemode| gamemode.name()).collect::<Vec<&str>>().join(", ")
+		));
+		messages
+	}
+	
+	/** The score of each team in team games, of each player in free for all games, or of all players together when holding zones against monsters. Highest first */
+	pub fn scores(&self) -> Vec<(String, u64)> {
+		let mut scores: Vec<(String, u64)> = match self.gamemode {
+			GameMode::Teams | GameMode::CaptureTheFlag | GameMode::KingOfTheHill => self.teams.iter()
+				.enumerate()
+				.map(|(index, team)| (team_name(index).to_string(), team.score))
+				.collect(),
+			GameMode::PvP => self.players.iter()
+				.map(|(playerid, player)| (playerid.to_string(), player.kills))
+				.collect(),
+			GameMode::ControlPoints => vec![("players".to_string(), self.coop_score)],
+			GameMode::Survival | GameMode::PillarDefence | GameMode::Escort | GameMode::Editor => Vec::new()
+		};
+		scores.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+		scores
 	}
 	
 	
@@ -502,13 +1350,45 @@ impl World {
 			sprites.insert(*pos, vec![*sprite]);
 		}
 		for creature in self.creatures.values() {
-			sprites.entry(creature.pos).or_insert_with(Vec::new).push(creature.sprite);
+			sprites.entry(creature.pos).or_default().push(creature.sprite);
 		}
 		for (pos, item) in self.items.iter() {
-			sprites.entry(*pos).or_insert_with(Vec::new).push(item.sprite());
+			sprites.entry(*pos).or_default().push(item.sprite());
+		}
+		let mut cursors: HashSet<Pos> = HashSet::new();
+		if let Some(editor) = &self.editor {
+			for (pos, sprite) in editor.sprites() {
+				sprites.entry(pos).or_default().push(sprite);
+			}
+			for pos in editor.changed_ground() {
+				sprites.entry(*pos).or_default();
+			}
+			for player in self.players.values().filter(|player| !player.is_bot) {
+				cursors.insert(player.cursor);
+				sprites.entry(player.cursor).or_default();
+			}
+		}
+		// zones only colour the background, so they go below the ground
+		let mut zone_sprites: HashMap<Pos, Sprite> = HashMap::new();
+		for zone in self.zones.iter() {
+			let sprite = Sprite::new(match &zone.owner {
+				Some(Alignment::Team(team)) => zone_sprite(*team),
+				Some(Alignment::Players) => "zone_players",
+				Some(Alignment::Monsters) => "zone_monsters",
+				Some(Alignment::Player(_)) | None => "zone"
+			});
+			for pos in zone.positions() {
+				zone_sprites.insert(pos, sprite);
+				sprites.entry(pos).or_default();
+			}
 		}
 		sprites.into_iter().filter_map(|(pos, mut sprs)| {
 			sprs.push(self.ground.get(pos)?.sprite());
+			sprs.extend(zone_sprites.get(&pos));
+			// the cursor colours the background, so whatever is under it stays visible
+			if cursors.contains(&pos) {
+				sprs.push(Sprite::new("cursor"));
+			}
 			Some((pos, sprs))
 		}).collect()
 	}
@@ -526,12 +1406,22 @@ impl World {
 		} else {None}
 	}
 	
+	/** What every (non-bot) player should be sent after an update. The field is only included for new players and after a reset; otherwise only changed cells are included */
 	pub fn view(&mut self) -> HashMap<PlayerId, WorldMessage> {
 		let dynamic_sprites = self.draw_dynamic();
 		let changes = self.draw_changes(dynamic_sprites.clone());
 		let mut field = None;
+		let scores = self.scores();
+		let game_over_messages = if self.round_state == RoundState::GameOver(GAME_OVER_DURATION) {
+			self.game_over_messages()
+		} else {
+			Vec::new()
+		};
 		let mut views: HashMap<PlayerId, WorldMessage> = HashMap::new();
 		for (playerid, player) in self.players.iter_mut() {
+			if player.is_bot {
+				continue;
+			}
 			let mut wm = WorldMessage::default();
 			if changes.is_some() && !player.is_new {
 				wm.change = changes.clone();
@@ -551,26 +1441,199 @@ impl World {
 						.collect::<Vec<&'static str>>(),
 					body.selected_weapon
 				))
+			} else if self.editor.is_some() {
+				wm.pos = Some(player.cursor);
+			}
+			if !scores.is_empty() {
+				wm.scores = Some(scores.clone());
 			}
-			if self.round_state == RoundState::GameOver(Duration(1)) {
-				wm.sounds = Some(vec![("restart".to_string(), "---- Starting new session ----".to_string(), None)]);
+			let mut sounds: SoundMessage = self.announcements.iter()
+				.map(|text| ("event".to_string(), text.clone(), None))
+				.collect();
+			if !game_over_messages.is_empty() {
+				sounds.extend(game_over_messages.iter().map(|text| ("gameover".to_string(), text.clone(), None)));
+			} else if self.round_state == RoundState::GameOver(Duration(1)) {
+				sounds.push(("restart".to_string(), "---- Starting new session ----".to_string(), None));
 			} else if self.round_state == RoundState::Paused(Duration(1)) {
-				wm.sounds = Some(vec![("wave".to_string(), format!("**** Wave {} ****", self.wave), None)]);
+				sounds.push(("wave".to_string(), format!("**** Wave {} ****", self.wave), None));
+			}
+			if !sounds.is_empty() {
+				wm.sounds = Some(sounds);
 			}
 			views.insert(playerid.clone(), wm);
 		}
 		self.drawing = Some(dynamic_sprites);
+		self.announcements.clear();
 		views
 	}
 	
+	/** What happened since the last time this was called, for the event log */
+	pub fn take_events(&mut self) -> Vec<GameEvent> {
+		std::mem::take(&mut self.events)
+	}
+	
+	pub fn wave(&self) -> usize {
+		self.wave
+	}
+	
+	pub fn gamemode(&self) -> GameMode {
+		self.gamemode
+	}
+	
+	pub fn map_name(&self) -> &str {
+		self.rotation.current_name()
+	}
+	
+	pub fn is_round_over(&self) -> bool {
+		matches!(self.round_state, RoundState::GameOver(_))
+	}
+	
+	pub fn is_round_won(&self) -> bool {
+		self.is_round_over() && self.round_won
+	}
+	
+	pub fn round_state(&self) -> RoundState {
+		self.round_state
+	}
+	
+	/** The creature that a player currently controls, if it's alive */
+	pub fn player_body(&self, playerid: &PlayerId) -> Option<&Creature> {
+		self.creatures.get(&self.players.get(playerid)?.body)
+	}
+	
+	pub fn creatures(&self) -> impl Iterator<Item=&Creature> {
+		self.creatures.values()
+	}
+	
+	pub fn zones(&self) -> &[Zone] {
+		&self.zones
+	}
+	
+	pub fn bullets(&self) -> &[Bullet] {
+		&self.bullets
+	}
+	
+	pub fn items(&self) -> &HashMap<Pos, Item> {
+		&self.items
+	}
+	
+	pub fn add_item(&mut self, pos: Pos, item: Item) {
+		self.items.insert(pos, item);
+	}
+	
+	pub fn tile(&self, pos: Pos) -> Option<Tile> {
+		self.ground.get(pos).cloned()
+	}
+	
+	pub fn phase_times(&self) -> &PhaseTimes {
+		&self.phase_times
+	}
+	
+	/** The number of players that are not bots */
 	pub fn nplayers(&self) -> usize {
-		self.players.len()
+		self.players.values().filter(|player| !player.is_bot).count()
+	}
+}
+
+
+fn side_name(alignment: &Alignment) -> String {
+	match alignment {
+		Alignment::Team(team) => format!("Team {}", team_name(*team)),
+		Alignment::Player(playerid) => playerid.to_string(),
+		Alignment::Players => "The players".to_string(),
+		Alignment::Monsters => "The monsters".to_string()
+	}
+}
+
+/** Pick up an enemy flag, return the own flag or capture a flag when a player steps on something. Returns what should be announced */
+fn touch_flags(creature: &mut Creature, items: &mut HashMap<Pos, Item>, ground: &Grid<Tile>, teams: &mut [Team]) -> Option<String> {
+	let Alignment::Team(own) = creature.alignment else {
+		return None;
+	};
+	let pos = creature.pos;
+	let name = creature.player_id()?.to_string();
+	let own_home = teams.get(own)?.flag;
+	match (items.get(&pos), own_home) {
+		(Some(Item::Flag(team)), _) if *team != own && creature.carried.is_none() => {
+			let team = *team;
+			items.remove(&pos);
+			creature.carried = Some(Item::Flag(team));
+			return Some(format!("{} took the {} flag", name, team_name(team)));
+		}
+		(Some(Item::Flag(team)), Some(home)) if *team == own && home != pos => {
+			items.remove(&pos);
+			return_flag(items, ground, own, home);
+			return Some(format!("{} returned the {} flag", name, team_name(own)));
+		}
+		_ => {}
+	}
+	if let Some(Item::Flag(team)) = creature.carried {
+		let own_flag_home = own_home.is_none_or(|home| items.get(&home) == Some(&Item::Flag(own)));
+		if teams[own].base == Some(pos) && own_flag_home {
+			creature.carried = None;
+			teams[own].score += 1;
+			if let Some(home) = teams.get(team).and_then(|team| team.flag) {
+				return_flag(items, ground, team, home);
+			}
+			return Some(format!("{} captured the {} flag for team {}", name, team_name(team), team_name(own)));
+		}
+	}
+	None
+}
+
+/** Put a flag back at its home. Whatever was dropped there is moved out of the way */
+fn return_flag(items: &mut HashMap<Pos, Item>, ground: &Grid<Tile>, team: usize, home: Pos) {
+	if let Some(other) = items.insert(home, Item::Flag(team)) {
+		put_item_near(items, ground, home, other);
 	}
 }
 
+/** Put an item on the nearest open place from pos that has no item yet */
+fn put_item_near(items: &mut HashMap<Pos, Item>, ground: &Grid<Tile>, pos: Pos, item: Item) {
+	let mut frontier = VecDeque::from([pos]);
+	let mut seen = HashSet::from([pos]);
+	while let Some(place) = frontier.pop_front() {
+		if let Entry::Vacant(free) = items.entry(place) {
+			free.insert(item);
+			return;
+		}
+		for next in Direction::DIRECTIONS.iter().map(|dir| place + *dir) {
+			if ground.get(next).is_some_and(|tile| !tile.blocking()) && seen.insert(next) {
+				frontier.push_back(next);
+			}
+		}
+	}
+}
+
+/** A random open place in the spawn zones. Teams use their own zones if they have them, and the zones without a team otherwise */
+fn spawn_position(zones: &[SpawnZone], ground: &Grid<Tile>, team: Option<usize>) -> Option<Pos> {
+	let has_own = team.is_some() && zones.iter().any(|zone| zone.team == team);
+	let positions: Vec<Pos> = zones.iter()
+		.filter(|zone| team.is_none() || zone.team == team || !has_own && zone.team.is_none())
+		.flat_map(|zone| zone.positions())
+		.filter(|pos| ground.get(*pos).is_some_and(|tile| !tile.blocking()))
+		.collect();
+	positions.choose(&mut rng()).cloned()
+}
+
+/** Whether a creature may heal and hide in the sanctuary it stands in. In team games only the owning team may */
+fn can_use_sanctuary(owners: &HashMap<Pos, usize>, creature: &Creature) -> bool {
+	owners.get(&creature.pos).is_none_or(|team| creature.alignment == Alignment::Team(*team))
+}
+
+/** Whether the ground allows a creature to step to newpos. Gates can only be passed from the sanctuary with full health */
+fn can_walk(ground: &Grid<Tile>, creature: &Creature, newpos: Pos) -> bool {
+	match ground.get(newpos) {
+		Some(tile) =>
+			!tile.blocking()
+				|| tile == &Tile::Gate
+					&& ground.get(creature.pos) == Some(&Tile::Sanctuary)
+					&& creature.has_full_health(),
+		None => false
+	}
+}
 
 fn draw_field(size: Pos, tiles: &Grid<Tile>, sprites: &HashMap<Pos, Vec<Sprite>>) -> FieldMessage {
-	println!("redrawing field");
 	let mut values :Vec<usize> = Vec::with_capacity((size.x * size.y) as usize);
 	let mut mapping: Vec<Vec<Sprite>> = Vec::new();
 	for y in 0..size.y {
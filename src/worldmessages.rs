@@ -61,6 +61,7 @@ worldmessages!(
 	weapons, WeaponsMessage, "weapons", true;
 	health, HealthMessage, "health", true;
 	ground, GroundMessage, "ground", true;
+	scores, ScoresMessage, "scores", true;
 	sounds, SoundMessage, "messages", false;
 );
 
@@ -69,6 +70,7 @@ pub type ChangeMessage = Vec<(Pos, Vec<Sprite>)>;
 pub type HealthMessage = (Health, Health);
 pub type WeaponsMessage = (Vec<&'static str>, usize);
 pub type GroundMessage = Vec<String>;
+pub type ScoresMessage = Vec<(String, u64)>;
 pub type SoundMessage = Vec<(String, String, Option<Value>)>;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
@@ -89,7 +91,7 @@ impl MessageCache {
 	pub fn trim(&mut self, player: &PlayerId, msg: &mut WorldMessage){
 		if let Some(cached) = self.cache.get_mut(player){
 			msg.remove_old(cached);
-			cached.add(&msg);
+			cached.add(msg);
 		} else {
 			self.cache.insert(player.clone(), msg.clone());
 		}
@@ -0,0 +1,99 @@
+
+use crate::{
+	Pos,
+	pos::Distance,
+	creature::Alignment
+};
+
+/** How many ticks a side needs to stand in a zone alone to capture it */
+pub const CAPTURE_TIME: i64 = 30;
+
+/** A control point that can be captured by standing in it without enemies around */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Zone {
+	pub center: Pos,
+	pub radius: Distance,
+	pub owner: Option<Alignment>,
+	capturer: Option<Alignment>,
+	progress: i64
+}
+
+impl Zone {
+
+	pub fn new(center: Pos, radius: Distance) -> Self {
+		Self {
+			center,
+			radius,
+			owner: None,
+			capturer: None,
+			progress: 0
+		}
+	}
+
+	pub fn contains(&self, pos: Pos) -> bool {
+		self.center.distance_to(pos) <= self.radius
+	}
+
+	pub fn positions(&self) -> impl Iterator<Item=Pos> + '_ {
+		let r = self.radius.0;
+		(-r..=r)
+			.flat_map(move |dy| (-r..=r).map(move |dx| self.center + Pos::new(dx, dy)))
+			.filter(move |pos| self.contains(*pos))
+	}
+
+	/** Advance the capture by one tick, given the alignments of everyone standing in the zone. Returns whether the owner changed */
+	pub fn update(&mut self, present: &[Alignment]) -> bool {
+		let Some(side) = present.first() else {
+			return false;
+		};
+		if present.iter().any(|alignment| alignment != side) {
+			// contested
+			return false;
+		}
+		if self.capturer.as_ref() != Some(side) {
+			// someone else has to be pushed out first
+			if self.progress > 0 {
+				self.progress -= 1;
+				return self.progress == 0 && self.owner.take().is_some();
+			}
+			self.capturer = Some(side.clone());
+		}
+		self.progress = (self.progress + 1).min(CAPTURE_TIME);
+		if self.progress == CAPTURE_TIME && self.owner.as_ref() != Some(side) {
+			self.owner = Some(side.clone());
+			return true;
+		}
+		false
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_capture_and_take_over() {
+		let mut zone = Zone::new(Pos::new(5, 5), Distance(1));
+		assert!(zone.contains(Pos::new(5, 6)));
+		assert!(!zone.contains(Pos::new(6, 6)));
+		assert_eq!(zone.positions().count(), 5);
+		for _ in 0..CAPTURE_TIME {
+			assert_eq!(zone.owner, None);
+			zone.update(&[Alignment::Team(0)]);
+		}
+		assert_eq!(zone.owner, Some(Alignment::Team(0)));
+		// contested zones don't change
+		zone.update(&[Alignment::Team(0), Alignment::Team(1)]);
+		assert_eq!(zone.owner, Some(Alignment::Team(0)));
+		// the other team first neutralizes the zone, then captures it
+		for _ in 0..CAPTURE_TIME {
+			zone.update(&[Alignment::Team(1)]);
+		}
+		assert_eq!(zone.owner, None);
+		for _ in 0..CAPTURE_TIME {
+			zone.update(&[Alignment::Team(1), Alignment::Team(1)]);
+		}
+		assert_eq!(zone.owner, Some(Alignment::Team(1)));
+	}
+}
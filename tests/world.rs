@@ -0,0 +1,808 @@
+
+use std::time::Duration;
+use battilde::{
+	World,
+	GameMode,
+	MapType,
+	MapTemplate,
+	PlayerId,
+	Pos,
+	Direction,
+	Control,
+	Sprite,
+	seed_rng,
+	creature::{Creature, Health, Alignment, Mind},
+	item::Item,
+	tile::{Tile, FloorType, ObstacleType},
+	world::RoundState,
+	team::RoundRules,
+	zone,
+	eventlog::GameEvent,
+};
+
+/** A world with scripted players that can be stepped and inspected */
+struct Harness {
+	world: World
+}
+
+impl Harness {
+
+	fn new(gamemode: GameMode, map: &str) -> Self {
+		seed_rng(1);
+		let template: MapTemplate = json5::from_str(map).expect("invalid test map");
+		Self {
+			world: World::new(gamemode, MapType::Custom(Box::new(template)))
+		}
+	}
+
+	/** Add a player and step once so their body spawns */
+	fn join(&mut self, name: &str) -> PlayerId {
+		let player = PlayerId(name.to_string());
+		self.world.add_player(&player, Sprite::Player("a", name.chars().next().unwrap())).unwrap();
+		self.world.update();
+		player
+	}
+
+	fn step(&mut self, n: usize) {
+		for _ in 0..n {
+			self.world.update();
+		}
+	}
+
+	/** Give each control in turn to the player, one per step */
+	fn script(&mut self, player: &PlayerId, controls: &[Control]) {
+		for control in controls {
+			self.world.control_player(player.clone(), control.clone()).unwrap();
+			self.world.update();
+		}
+	}
+
+	fn body(&self, player: &PlayerId) -> &Creature {
+		self.world.player_body(player).expect("player has no body")
+	}
+
+	fn pos(&self, player: &PlayerId) -> Pos {
+		self.body(player).pos
+	}
+
+	fn health(&self, player: &PlayerId) -> Health {
+		self.body(player).health
+	}
+
+	fn heal_fully(&mut self, player: &PlayerId) {
+		for _ in 0..100 {
+			if self.health(player) == self.body(player).max_health {
+				return;
+			}
+			self.step(1);
+		}
+		panic!("player did not heal");
+	}
+}
+
+/** A corridor with a sanctuary, a gate and open ground */
+const CORRIDOR: &str = r#"{
+	size: [12, 3],
+	spawnpoint: [1, 1],
+	monsterspawn: [[10, 1]],
+	ground: [
+		"XXXXXXXXXXXX",
+		"X++=.......X",
+		"XXXXXXXXXXXX",
+	],
+	creatures: [],
+}"#;
+
+const EAST: Control = Control::Move(Direction::East);
+const WEST: Control = Control::Move(Direction::West);
+
+
+#[test]
+fn test_gate_needs_full_health_to_leave() {
+	let mut h = Harness::new(GameMode::PvP, CORRIDOR);
+	let player = h.join("alice");
+	assert_eq!(h.pos(&player), Pos::new(1, 1));
+	assert!(h.health(&player) != h.body(&player).max_health);
+	h.script(&player, &[EAST, EAST]);
+	assert_eq!(h.pos(&player), Pos::new(2, 1), "a wounded player can not pass the gate");
+	h.heal_fully(&player);
+	h.script(&player, &[EAST, EAST]);
+	assert_eq!(h.pos(&player), Pos::new(4, 1));
+	assert_eq!(h.world.tile(h.pos(&player)), Some(Tile::from_char('.').unwrap()));
+	h.script(&player, &[WEST]);
+	assert_eq!(h.pos(&player), Pos::new(4, 1), "the gate can not be entered from outside");
+}
+
+#[test]
+fn test_sanctuary_heals_and_outside_does_not() {
+	let mut h = Harness::new(GameMode::PvP, CORRIDOR);
+	let player = h.join("alice");
+	let before = h.health(&player);
+	h.step(5);
+	assert_eq!(h.health(&player), Health(before.0 + 10));
+	h.heal_fully(&player);
+	h.step(3);
+	assert_eq!(h.health(&player), h.body(&player).max_health);
+
+	// both walk out and the first shoots the second
+	h.script(&player, &[Control::NextWeapon]);
+	h.script(&player, &[EAST; 9]);
+	assert_eq!(h.pos(&player), Pos::new(10, 1));
+	let target = h.join("bob");
+	h.heal_fully(&target);
+	h.script(&target, &[EAST, EAST, EAST]);
+	assert_eq!(h.pos(&target), Pos::new(4, 1));
+	h.world.control_player(player.clone(), Control::Shoot(Some(Direction::West))).unwrap();
+	h.step(1);
+	assert!(!h.world.bullets().is_empty());
+	h.step(5);
+	assert!(h.world.bullets().is_empty());
+	let wounded = h.health(&target);
+	assert!(wounded != h.body(&target).max_health);
+	h.step(5);
+	assert_eq!(h.health(&target), wounded);
+}
+
+#[test]
+fn test_health_item_is_picked_up() {
+	let mut h = Harness::new(GameMode::PvP, CORRIDOR);
+	let player = h.join("alice");
+	h.heal_fully(&player);
+	h.script(&player, &[Control::NextWeapon]);
+	h.script(&player, &[EAST; 9]);
+	let target = h.join("bob");
+	h.heal_fully(&target);
+	h.script(&target, &[EAST, EAST, EAST]);
+	h.world.control_player(player.clone(), Control::Shoot(Some(Direction::West))).unwrap();
+	h.step(6);
+	assert!(h.health(&target) != h.body(&target).max_health);
+	h.world.add_item(Pos::new(5, 1), Item::Health);
+	h.script(&target, &[EAST]);
+	assert_eq!(h.pos(&target), Pos::new(5, 1));
+	assert!(h.world.items().is_empty());
+	assert_eq!(h.health(&target), h.body(&target).max_health);
+}
+
+#[test]
+fn test_pillar_destroyed_ends_round() {
+	let mut h = Harness::new(GameMode::PillarDefence, r#"{
+		size: [12, 3],
+		spawnpoint: [1, 1],
+		monsterspawn: [[10, 1]],
+		ground: [
+			"XXXXXXXXXXXX",
+			"X++=.......X",
+			"XXXXXXXXXXXX",
+		],
+		creatures: [[[6, 1], "Pillar"], [[7, 1], "Troll"]],
+	}"#);
+	let player = h.join("alice");
+	assert!(matches!(h.world.round_state(), RoundState::Paused(_) | RoundState::Running));
+	for _ in 0..1000 {
+		if h.world.is_round_over() {
+			break;
+		}
+		h.step(1);
+	}
+	assert!(matches!(h.world.round_state(), RoundState::GameOver(_)));
+	assert!(h.world.player_body(&player).is_some());
+	assert!(!h.world.creatures().any(|c| c.is_building));
+}
+
+#[test]
+fn test_waves_progress_when_monsters_are_killed() {
+	let mut h = Harness::new(GameMode::Survival, CORRIDOR);
+	let player = h.join("alice");
+	assert_eq!(h.world.wave(), 1);
+	assert!(matches!(h.world.round_state(), RoundState::Paused(_)));
+	h.step(30);
+	assert_eq!(h.world.round_state(), RoundState::Running);
+	assert!(h.world.creatures().any(|c| !c.is_player()));
+	h.heal_fully(&player);
+
+	// hold the corridor until the wave is cleared
+	h.script(&player, &[Control::NextWeapon, EAST, EAST, EAST]);
+	assert_eq!(h.pos(&player), Pos::new(4, 1));
+	for _ in 0..1000 {
+		if h.world.wave() > 1 {
+			break;
+		}
+		h.script(&player, &[Control::Shoot(Some(Direction::East))]);
+	}
+	assert_eq!(h.world.wave(), 2);
+	assert!(!h.world.is_round_over());
+}
+
+#[test]
+fn test_teams_spawn_apart_and_score_kills() {
+	let mut h = Harness::new(GameMode::Teams, r#"{
+		size: [14, 3],
+		spawnpoint: [6, 1],
+		monsterspawn: [],
+		teamspawns: [[1, 1], [12, 1]],
+		ground: [
+			"XXXXXXXXXXXXXX",
+			"X+=........=+X",
+			"XXXXXXXXXXXXXX",
+		],
+		creatures: [],
+	}"#);
+	h.world.set_round_rules(RoundRules {teams: 2, score_limit: Some(1), time_limit: None, waves: None});
+	let red = h.join("alice");
+	let blue = h.join("bob");
+	assert_eq!(h.pos(&red), Pos::new(1, 1));
+	assert_eq!(h.pos(&blue), Pos::new(12, 1));
+	assert_eq!(h.body(&red).sprite, Sprite::Player("r", 'a'));
+	assert_eq!(h.body(&blue).sprite, Sprite::Player("b", 'b'));
+
+	// a third player is balanced into the first team and can switch
+	let switcher = h.join("carol");
+	assert_eq!(h.pos(&switcher), Pos::new(1, 1));
+	assert_eq!(h.world.teammates(&switcher).unwrap(), vec![red.clone(), switcher.clone()]);
+	h.script(&switcher, &[Control::Team(1)]);
+	assert_eq!(h.pos(&switcher), Pos::new(12, 1));
+	assert_eq!(h.world.teammates(&blue).unwrap(), vec![blue.clone(), switcher.clone()]);
+	assert!(h.world.teammates(&PlayerId("nobody".to_string())).is_err());
+	assert!(h.world.control_player(switcher.clone(), Control::Team(2)).is_err());
+
+	h.heal_fully(&red);
+	h.heal_fully(&blue);
+	h.script(&red, &[Control::NextWeapon, EAST, EAST]);
+	h.script(&blue, &[WEST, WEST]);
+	assert_eq!(h.pos(&red), Pos::new(3, 1));
+	assert_eq!(h.pos(&blue), Pos::new(10, 1));
+	for _ in 0..100 {
+		if h.world.is_round_over() {
+			break;
+		}
+		h.script(&red, &[Control::Shoot(Some(Direction::East))]);
+	}
+	assert!(h.world.is_round_over());
+	assert_eq!(h.world.scores(), vec![("red".to_string(), 1), ("blue".to_string(), 0)]);
+}
+
+#[test]
+fn test_round_time_limit_is_in_game_time() {
+	let mut h = Harness::new(GameMode::PvP, r#"{
+		size: [14, 3],
+		spawnpoint: [6, 1],
+		monsterspawn: [],
+		ground: [
+			"XXXXXXXXXXXXXX",
+			"X............X",
+			"XXXXXXXXXXXXXX",
+		],
+		creatures: [],
+	}"#);
+	h.world.set_step_duration(Duration::from_millis(100));
+	h.world.set_round_rules(RoundRules {time_limit: Some(Duration::from_secs(2)), ..RoundRules::default()});
+	h.join("alice");
+	h.step(9);
+	assert!(!h.world.is_round_over());
+	// slower steps make the rest of the round take fewer of them
+	h.world.set_step_duration(Duration::from_millis(1000));
+	h.step(1);
+	assert!(!h.world.is_round_over());
+	h.step(1);
+	assert!(h.world.is_round_over());
+}
+
+#[test]
+fn test_rounds_end_on_maps_narrower_than_the_game_over_text() {
+	let mut h = Harness::new(GameMode::PvP, r#"{
+		size: [6, 3],
+		spawnpoint: [2, 1],
+		monsterspawn: [],
+		ground: [
+			"XXXXXX",
+			"X....X",
+			"XXXXXX",
+		],
+		creatures: [],
+	}"#);
+	h.world.set_round_rules(RoundRules {time_limit: Some(Duration::from_millis(100)), ..RoundRules::default()});
+	h.join("alice");
+	h.step(2);
+	assert!(h.world.is_round_over());
+	h.world.take_events();
+	h.step(60);
+	assert!(h.world.take_events().iter().any(|event| matches!(event, GameEvent::Reset{..})));
+}
+
+#[test]
+fn test_queued_round_rules_wait_for_the_next_round() {
+	let mut h = Harness::new(GameMode::Teams, r#"{
+		size: [14, 3],
+		spawnpoint: [6, 1],
+		monsterspawn: [],
+		teamspawns: [[1, 1], [12, 1]],
+		ground: [
+			"XXXXXXXXXXXXXX",
+			"X+=........=+X",
+			"XXXXXXXXXXXXXX",
+		],
+		creatures: [],
+	}"#);
+	h.join("alice");
+	let blue = h.join("bob");
+	h.world.queue_round_rules(RoundRules {teams: 1, score_limit: Some(5), ..RoundRules::default()});
+	assert_eq!(h.world.round_rules(), &RoundRules {teams: 1, score_limit: Some(5), ..RoundRules::default()});
+	h.step(1);
+	assert_eq!(h.world.scores().len(), 2);
+	assert_eq!(h.pos(&blue), Pos::new(12, 1));
+	h.world.reset();
+	h.step(1);
+	assert_eq!(h.world.scores().len(), 1);
+	assert_eq!(h.pos(&blue), Pos::new(1, 1));
+}
+
+#[test]
+fn test_capture_the_flag() {
+	let mut h = Harness::new(GameMode::CaptureTheFlag, r#"{
+		size: [14, 3],
+		spawnpoint: [6, 1],
+		monsterspawn: [],
+		teamspawns: [[1, 1], [12, 1]],
+		flags: [[4, 1], [9, 1]],
+		ground: [
+			"XXXXXXXXXXXXXX",
+			"X+=........=+X",
+			"XXXXXXXXXXXXXX",
+		],
+		creatures: [],
+	}"#);
+	h.world.set_round_rules(RoundRules {teams: 2, score_limit: Some(2), time_limit: None, waves: None});
+	assert_eq!(h.world.items().get(&Pos::new(4, 1)), Some(&Item::Flag(0)));
+	assert_eq!(h.world.items().get(&Pos::new(9, 1)), Some(&Item::Flag(1)));
+	let red = h.join("alice");
+	let blue = h.join("bob");
+	h.heal_fully(&red);
+	h.heal_fully(&blue);
+
+	// walking over the own flag at home does nothing
+	h.script(&red, &[EAST, EAST, EAST]);
+	assert_eq!(h.pos(&red), Pos::new(4, 1));
+	assert_eq!(h.body(&red).carried, None);
+
+	// the blue flag is taken and dropped when its carrier dies
+	h.script(&red, &[EAST; 5]);
+	assert_eq!(h.pos(&red), Pos::new(9, 1));
+	assert_eq!(h.body(&red).carried, Some(Item::Flag(1)));
+	assert!(!h.world.items().contains_key(&Pos::new(9, 1)));
+	h.script(&red, &[WEST, Control::Suicide]);
+	assert_eq!(h.world.items().get(&Pos::new(8, 1)), Some(&Item::Flag(1)));
+
+	// blue returns their flag
+	h.script(&blue, &[WEST, WEST, WEST, WEST]);
+	assert_eq!(h.pos(&blue), Pos::new(8, 1));
+	assert_eq!(h.world.items().get(&Pos::new(9, 1)), Some(&Item::Flag(1)));
+	assert!(!h.world.items().contains_key(&Pos::new(8, 1)));
+	h.script(&blue, &[WEST, WEST, WEST, WEST]);
+	assert_eq!(h.pos(&blue), Pos::new(4, 1));
+	assert_eq!(h.body(&blue).carried, Some(Item::Flag(0)));
+
+	// and brings the red flag home to score
+	h.script(&blue, &[EAST; 5]);
+	assert_eq!(h.pos(&blue), Pos::new(9, 1));
+	assert_eq!(h.body(&blue).carried, None);
+	assert_eq!(h.world.items().get(&Pos::new(4, 1)), Some(&Item::Flag(0)));
+	assert_eq!(h.world.scores()[0], ("blue".to_string(), 1));
+	assert!(!h.world.is_round_over());
+}
+
+#[test]
+fn test_flags_dropped_on_a_home_are_not_lost() {
+	let mut h = Harness::new(GameMode::CaptureTheFlag, r#"{
+		size: [14, 4],
+		spawnpoint: [6, 1],
+		monsterspawn: [],
+		teamspawns: [[1, 1], [12, 1]],
+		flags: [[4, 1], [9, 1]],
+		ground: [
+			"XXXXXXXXXXXXXX",
+			"X+=........=+X",
+			"XX..........XX",
+			"XXXXXXXXXXXXXX",
+		],
+		creatures: [],
+	}"#);
+	h.world.set_round_rules(RoundRules {teams: 2, score_limit: Some(2), time_limit: None, waves: None});
+	let red = h.join("alice");
+	let blue = h.join("bob");
+	h.heal_fully(&red);
+	h.heal_fully(&blue);
+	let flag_at = |h: &Harness, team: usize| h.world.items().iter()
+		.find(|(_, item)| **item == Item::Flag(team))
+		.map(|(pos, _)| *pos);
+
+	// blue takes the red flag and steps aside
+	h.script(&blue, &[WEST; 8]);
+	assert_eq!(h.body(&blue).carried, Some(Item::Flag(0)));
+	h.script(&blue, &[Control::Move(Direction::South)]);
+	assert_eq!(h.pos(&blue), Pos::new(4, 2));
+
+	// red takes the blue flag and dies on the empty red home
+	h.script(&red, &[EAST; 8]);
+	assert_eq!(h.body(&red).carried, Some(Item::Flag(1)));
+	h.script(&red, &[WEST; 5]);
+	assert_eq!(h.pos(&red), Pos::new(4, 1));
+	h.script(&red, &[Control::Suicide]);
+	assert_eq!(flag_at(&h, 1), Some(Pos::new(4, 1)));
+
+	// the red flag is dropped next to a health pickup instead of on it
+	h.world.add_item(Pos::new(4, 2), Item::Health);
+	h.script(&blue, &[Control::Suicide]);
+	assert_eq!(h.world.items().get(&Pos::new(4, 2)), Some(&Item::Health));
+	let dropped = flag_at(&h, 0).expect("the red flag is gone");
+	assert_ne!(dropped, Pos::new(4, 1));
+
+	// returning the red flag moves the blue flag out of the way
+	h.heal_fully(&red);
+	h.script(&red, &[EAST, EAST, Control::Move(Direction::South)]);
+	while flag_at(&h, 0) != Some(Pos::new(4, 1)) {
+		assert!(h.pos(&red).x < 8, "red never reached the dropped flag at {:?}", dropped);
+		h.script(&red, &[EAST]);
+	}
+	let blue_flag = flag_at(&h, 1).expect("the blue flag is gone");
+	assert_ne!(blue_flag, Pos::new(4, 1));
+	assert_eq!(h.world.items().values().filter(|item| matches!(item, Item::Flag(_))).count(), 2);
+}
+
+#[test]
+fn test_king_of_the_hill_zone_is_captured_and_scores() {
+	let mut h = Harness::new(GameMode::KingOfTheHill, r#"{
+		size: [14, 3],
+		spawnpoint: [6, 1],
+		monsterspawn: [],
+		teamspawns: [[1, 1], [12, 1]],
+		zones: [[[6, 1], 1]],
+		ground: [
+			"XXXXXXXXXXXXXX",
+			"X+=........=+X",
+			"XXXXXXXXXXXXXX",
+		],
+		creatures: [],
+	}"#);
+	h.world.set_round_rules(RoundRules {teams: 2, score_limit: Some(10), time_limit: None, waves: None});
+	let red = h.join("alice");
+	let blue = h.join("bob");
+	h.heal_fully(&red);
+	h.heal_fully(&blue);
+	h.script(&red, &[EAST; 5]);
+	assert_eq!(h.pos(&red), Pos::new(6, 1));
+	assert!(h.world.zones()[0].contains(h.pos(&red)));
+	h.step(zone::CAPTURE_TIME as usize);
+	assert_eq!(h.world.zones()[0].owner, Some(Alignment::Team(0)));
+
+	// an enemy in the zone stops the capture but not the scoring
+	h.script(&blue, &[WEST; 5]);
+	assert_eq!(h.pos(&blue), Pos::new(7, 1));
+	h.step(5);
+	assert_eq!(h.world.zones()[0].owner, Some(Alignment::Team(0)));
+	for _ in 0..20 {
+		if h.world.is_round_over() {
+			break;
+		}
+		h.step(1);
+	}
+	assert!(h.world.is_round_over());
+	assert_eq!(h.world.scores()[0], ("red".to_string(), 10));
+}
+
+#[test]
+fn test_monsters_holding_all_zones_end_round() {
+	let mut h = Harness::new(GameMode::ControlPoints, r#"{
+		size: [12, 3],
+		spawnpoint: [1, 1],
+		monsterspawn: [[10, 1]],
+		zones: [[[7, 1], 0]],
+		ground: [
+			"XXXXXXXXXXXX",
+			"X++=.......X",
+			"XXXXXXXXXXXX",
+		],
+		creatures: [],
+	}"#);
+	let player = h.join("alice");
+	for _ in 0..1000 {
+		if h.world.is_round_over() {
+			break;
+		}
+		h.step(1);
+	}
+	assert!(matches!(h.world.round_state(), RoundState::GameOver(_)));
+	assert_eq!(h.world.zones()[0].owner, Some(Alignment::Monsters));
+	assert!(h.world.player_body(&player).is_some());
+	assert_eq!(h.world.scores(), vec![("players".to_string(), 0)]);
+}
+
+#[test]
+fn test_payload_moves_with_escort_and_arrives() {
+	let mut h = Harness::new(GameMode::Escort, r#"{
+		size: [16, 5],
+		spawnpoint: [1, 1],
+		monsterspawn: [[14, 3]],
+		payload_path: [[6, 1], [14, 1]],
+		ground: [
+			"XXXXXXXXXXXXXXXX",
+			"X++=...........X",
+			"XXXXXXXXXXXXXXXX",
+			"X..............X",
+			"XXXXXXXXXXXXXXXX",
+		],
+		creatures: [],
+	}"#);
+	let payload_pos = |h: &Harness| h.world.creatures().find(|c| c.mind == Mind::Payload).unwrap().pos;
+	let player = h.join("alice");
+	h.heal_fully(&player);
+	assert_eq!(payload_pos(&h), Pos::new(6, 1), "the payload waits for an escort");
+	h.script(&player, &[EAST, EAST]);
+	for _ in 0..200 {
+		if h.world.is_round_over() {
+			break;
+		}
+		h.script(&player, &[EAST]);
+	}
+	assert!(h.world.is_round_over());
+	assert_eq!(payload_pos(&h), Pos::new(14, 1));
+}
+
+#[test]
+fn test_surviving_all_waves_wins_and_loads_next_map() {
+	let mut h = Harness::new(GameMode::Survival, CORRIDOR);
+	let first: MapTemplate = json5::from_str(CORRIDOR).unwrap();
+	let second: MapTemplate = json5::from_str(r#"{
+		size: [12, 3],
+		spawnpoint: [2, 1],
+		monsterspawn: [[10, 1]],
+		ground: [
+			"XXXXXXXXXXXX",
+			"X+++=......X",
+			"XXXXXXXXXXXX",
+		],
+		creatures: [],
+	}"#).unwrap();
+	h.world.set_maps(vec![
+		("first".to_string(), MapType::Custom(Box::new(first))),
+		("second".to_string(), MapType::Custom(Box::new(second)))
+	]).unwrap();
+	h.world.set_round_rules(RoundRules {waves: Some(1), ..RoundRules::default()});
+	let player = h.join("alice");
+	h.step(30);
+	h.heal_fully(&player);
+	h.script(&player, &[Control::NextWeapon, EAST, EAST, EAST]);
+	for _ in 0..1000 {
+		if h.world.is_round_over() {
+			break;
+		}
+		h.script(&player, &[Control::Shoot(Some(Direction::East))]);
+	}
+	assert!(h.world.is_round_won());
+	while h.world.is_round_over() {
+		h.step(1);
+	}
+	h.step(1);
+	assert_eq!(h.pos(&player), Pos::new(2, 1));
+	assert_eq!(h.world.wave(), 1);
+}
+
+#[test]
+fn test_votes_pick_next_map_and_gamemode() {
+	// without pillars to defend every round is over immediately
+	let mut h = Harness::new(GameMode::PillarDefence, CORRIDOR);
+	let first: MapTemplate = json5::from_str(CORRIDOR).unwrap();
+	h.world.set_maps(vec![
+		("corridor".to_string(), MapType::Custom(Box::new(first.clone()))),
+		("other".to_string(), MapType::Custom(Box::new(first.clone()))),
+		("third".to_string(), MapType::Custom(Box::new(first)))
+	]).unwrap();
+	let alice = h.join("alice");
+	let bob = h.join("bob");
+	let carol = h.join("carol");
+	assert!(h.world.is_round_over());
+	assert!(!h.world.is_round_won());
+
+	// without votes the next map is played, also after a lost round
+	while h.world.is_round_over() {
+		h.step(1);
+	}
+	assert_eq!(h.world.map_name(), "other");
+	h.step(1);
+	assert!(h.world.is_round_over());
+	assert!(h.world.control_player(alice.clone(), Control::Vote("nowhere".to_string())).is_err());
+	h.world.control_player(alice.clone(), Control::Vote("third".to_string())).unwrap();
+	h.world.control_player(bob.clone(), Control::Vote("other".to_string())).unwrap();
+	h.world.control_player(carol.clone(), Control::Vote("third".to_string())).unwrap();
+	h.world.control_player(bob.clone(), Control::Vote("tdm".to_string())).unwrap();
+	while h.world.is_round_over() {
+		h.step(1);
+	}
+	assert_eq!(h.world.map_name(), "third");
+	assert_eq!(h.world.gamemode(), GameMode::Teams);
+	assert!(!h.world.is_round_over());
+}
+
+/** Two sanctuaries, and a closed pocket at the bottom that monsters can't leave */
+const ARENA: &str = r#"{
+	name: "Arena",
+	author: "troido",
+	gamemodes: ["teams", "survival"],
+	legend: {"o": "sanctuary", "|": "gate"},
+	size: [14, 5],
+	spawnpoint: [6, 1],
+	monsterspawn: [{pos: [6, 3], last_wave: 1}, {pos: [8, 1], weight: 2, first_wave: 2}],
+	spawnzones: [{from: [1, 1], to: [2, 1], team: 0}, {from: [11, 1], to: [12, 1], team: 1}, {from: [1, 1]}],
+	ground: [
+		"XXXXXXXXXXXXXX",
+		"Xoo|......|ooX",
+		"XXXXXXXXXXXXXX",
+		"XXXXXX.XXXXXXX",
+		"XXXXXXXXXXXXXX",
+	],
+	creatures: [],
+}"#;
+
+#[test]
+fn test_map_spawn_zones_and_gamemodes() {
+	// the map isn't made for pvp, so it is played in its first gamemode
+	let mut h = Harness::new(GameMode::PvP, ARENA);
+	assert_eq!(h.world.gamemode(), GameMode::Teams);
+	assert_eq!(h.world.map_name(), "Arena");
+	assert_eq!(h.world.tile(Pos::new(3, 1)), Some(Tile::Gate));
+	let red = h.join("alice");
+	let blue = h.join("bob");
+	assert!([Pos::new(1, 1), Pos::new(2, 1)].contains(&h.pos(&red)));
+	assert!([Pos::new(11, 1), Pos::new(12, 1)].contains(&h.pos(&blue)));
+
+	// the next map that allows pvp is played in pvp again
+	let open: MapTemplate = json5::from_str(CORRIDOR).unwrap();
+	let arena: MapTemplate = json5::from_str(ARENA).unwrap();
+	h.world.set_maps(vec![
+		("arena".to_string(), MapType::Custom(Box::new(arena))),
+		("open".to_string(), MapType::Custom(Box::new(open)))
+	]).unwrap();
+	assert_eq!(h.world.gamemode(), GameMode::Teams);
+	h.world.change_map("open").unwrap();
+	assert_eq!(h.world.gamemode(), GameMode::PvP);
+}
+
+#[test]
+fn test_monster_spawns_follow_waves() {
+	let mut h = Harness::new(GameMode::Survival, ARENA);
+	h.join("alice");
+	assert_eq!(h.world.wave(), 1);
+	h.step(100);
+	// everyone of the first wave is stuck in the pocket
+	let monsters: Vec<Pos> = h.world.creatures().filter(|c| !c.is_player()).map(|c| c.pos).collect();
+	assert!(!monsters.is_empty());
+	assert!(monsters.iter().all(|pos| *pos == Pos::new(6, 3)), "{:?}", monsters);
+}
+
+#[test]
+fn test_changed_maps_are_used_from_the_next_round() {
+	let mut h = Harness::new(GameMode::PvP, CORRIDOR);
+	let alice = h.join("alice");
+	let changed: MapTemplate = json5::from_str(&CORRIDOR.replace("X++=.......X", "X++=...~...X")).unwrap();
+	let other: MapTemplate = json5::from_str(CORRIDOR).unwrap();
+	h.world.queue_maps(vec![
+		("other".to_string(), MapType::Custom(Box::new(other))),
+		("custom".to_string(), MapType::Custom(Box::new(changed)))
+	]).unwrap();
+	assert!(h.world.queue_maps(Vec::new()).is_err());
+	h.step(1);
+	assert_eq!(h.world.tile(Pos::new(7, 1)), Some(Tile::Floor(FloorType::Dirt)));
+	assert!(h.world.control_player(alice, Control::Reload).is_err());
+
+	// the map that was being played stays
+	h.world.reset();
+	assert_eq!(h.world.map_name(), "custom");
+	assert_eq!(h.world.tile(Pos::new(7, 1)), Some(Tile::Obstacle(ObstacleType::Water)));
+}
+
+#[test]
+fn test_editor_changes_the_map() {
+	let mut h = Harness::new(GameMode::Editor, CORRIDOR);
+	let builder = PlayerId("builder".to_string());
+	h.world.add_player(&builder, Sprite::Player("a", 'b')).unwrap();
+	h.step(1);
+	h.world.view();
+	assert!(h.world.player_body(&builder).is_none());
+	assert!(h.world.control_player(builder.clone(), Control::Vote("editor".to_string())).is_err());
+
+	for control in [EAST, EAST, EAST, EAST, Control::PlaceTile("water".to_string()), EAST, Control::PlaceCreature(battilde::creature::CreatureType::Pillar), Control::ToggleMonsterSpawn].iter() {
+		h.world.control_player(builder.clone(), control.clone()).unwrap();
+	}
+	h.step(1);
+	let view = h.world.view();
+	let changes = view[&builder].change.clone().unwrap();
+	assert!(changes.iter().any(|(pos, sprites)| *pos == Pos::new(5, 1) && sprites.contains(&Sprite::new("water"))), "{:?}", changes);
+	assert_eq!(h.world.tile(Pos::new(5, 1)), Some(Tile::Obstacle(ObstacleType::Water)));
+	let map = h.world.edited_map().unwrap();
+	assert_eq!(map.creatures, vec![(Pos::new(6, 1), battilde::creature::CreatureType::Pillar)]);
+	assert_eq!(map.monsterspawn.len(), 2);
+	// nothing is played in the editor
+	assert!(h.world.creatures().next().is_none());
+	assert_eq!(h.world.wave(), 0);
+
+	// edits only work in the editor
+	let mut h = Harness::new(GameMode::Survival, CORRIDOR);
+	let player = h.join("alice");
+	assert!(h.world.control_player(player, Control::SetSpawnpoint).is_err());
+	assert!(h.world.edited_map().is_none());
+}
+
+#[test]
+fn test_events_are_reported() {
+	let mut h = Harness::new(GameMode::Survival, CORRIDOR);
+	assert_eq!(h.world.take_events(), vec![GameEvent::Reset{map: "custom".to_string(), gamemode: GameMode::Survival}]);
+	let player = h.join("alice");
+	assert_eq!(h.world.take_events(), vec![GameEvent::WaveStart{wave: 1}]);
+	h.script(&player, &[Control::Suicide]);
+	assert_eq!(h.world.take_events(), vec![GameEvent::Death{player, cause: "suicide".to_string()}]);
+	assert!(h.world.take_events().is_empty());
+}
+
+#[test]
+fn test_operator_changes_map_and_gamemode() {
+	let mut h = Harness::new(GameMode::Survival, CORRIDOR);
+	let map: MapTemplate = json5::from_str(CORRIDOR).unwrap();
+	h.world.set_maps(vec![
+		("corridor".to_string(), MapType::Custom(Box::new(map.clone()))),
+		("other".to_string(), MapType::Custom(Box::new(map)))
+	]).unwrap();
+	let player = h.join("alice");
+	h.step(30);
+	assert_eq!(h.world.wave(), 1);
+	assert!(h.world.change_map("nowhere").is_err());
+	assert_eq!(h.world.wave(), 1);
+	h.world.change_map("other").unwrap();
+	assert_eq!(h.world.map_name(), "other");
+	assert_eq!(h.world.wave(), 0);
+	h.world.change_gamemode(GameMode::PvP);
+	assert_eq!(h.world.gamemode(), GameMode::PvP);
+	h.step(1);
+	assert_eq!(h.pos(&player), Pos::new(1, 1));
+}
+
+#[test]
+fn test_wounded_bot_retreats_towards_the_sanctuary() {
+	// the gate only lets creatures out, so the bot waits in front of it
+	let mut h = Harness::new(GameMode::PvP, r#"{
+		size: [12, 3],
+		spawnpoint: [9, 1],
+		monsterspawn: [],
+		ground: [
+			"XXXXXXXXXXXX",
+			"X++=.......X",
+			"XXXXXXXXXXXX",
+		],
+		creatures: [],
+	}"#);
+	h.world.set_min_players(1);
+	let bot = PlayerId("bot-1".to_string());
+	h.step(10);
+	assert_eq!(h.pos(&bot), Pos::new(4, 1));
+	for _ in 0..10 {
+		h.step(1);
+		assert_eq!(h.pos(&bot), Pos::new(4, 1), "the bot stays in front of the gate");
+	}
+
+	// an open sanctuary can be walked into to heal
+	let mut h = Harness::new(GameMode::PvP, r#"{
+		size: [12, 3],
+		spawnpoint: [9, 1],
+		monsterspawn: [],
+		ground: [
+			"XXXXXXXXXXXX",
+			"X++........X",
+			"XXXXXXXXXXXX",
+		],
+		creatures: [],
+	}"#);
+	h.world.set_min_players(1);
+	h.step(10);
+	assert_eq!(h.pos(&bot), Pos::new(2, 1));
+	let wounded = h.health(&bot);
+	h.step(5);
+	assert!(h.health(&bot).0 > wounded.0);
+}